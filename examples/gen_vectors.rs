@@ -0,0 +1,92 @@
+//! Writes the test vectors of the parameter sets with SHA-256, `n = m = 32`,
+//! and each `w` of `supported_winternitz`, for other implementations to check against.
+//!
+//! `cargo run --example gen_vectors -- vectors.json`
+//!
+//! Each vector is a tree of the height 2 derived from the master seed, each leaf
+//! signs the message `SHA-256(index as u8)`, the nodes are `HashOperation::new(b"node")`,
+//! the one time keys are bound to their leaves by the public seed, see `leaves_iter`,
+//! all values are hex, the public key and the signature are `to_bytes`,
+//! the path is `XmssPath::to_bytes`.
+
+#[path = "../src/sha256.rs"]
+#[allow(dead_code)]
+mod sha256;
+
+use std::{env, fs, fmt::Write};
+
+use digest::{
+    generic_array::typenum::{U32, U3, U7, U15, U31, U63, U127, U255},
+    Digest,
+};
+use w_ots::{HashOperation, WOtsPlus, XmssKeyPair, leaves_iter, supported_winternitz};
+
+use self::sha256::Sha256;
+
+const HEIGHT: usize = 2;
+
+fn vectors<A>(name: &str, output: &mut String)
+where
+    A: WOtsPlus<BlockLength = U32, MessageSize = U32, Digest = Sha256>,
+{
+    let node = HashOperation::<Sha256>::new(b"node");
+    let master_seed = Sha256::digest(name.as_bytes());
+    let (mut secret, public) = XmssKeyPair::generate::<A, _>(master_seed, HEIGHT, &node).unwrap();
+
+    writeln!(output, "  {{").unwrap();
+    writeln!(output, "    \"name\": \"{}\",", name).unwrap();
+    writeln!(output, "    \"seed\": \"{}\",", hex::encode(master_seed)).unwrap();
+    let public_seed = hex::encode(public.public_seed());
+    writeln!(output, "    \"public_seed\": \"{}\",", public_seed).unwrap();
+    writeln!(output, "    \"root\": \"{}\",", hex::encode(public.root())).unwrap();
+    writeln!(output, "    \"leaves\": [").unwrap();
+    let public_keys = leaves_iter::<A>(&master_seed, public.public_seed(), HEIGHT).unwrap();
+    for (index, public_key) in (0..).zip(public_keys) {
+        let message = Sha256::digest(&[index as u8]);
+        let signature = secret.sign_next(message, &node).unwrap();
+        assert!(public.verify(&signature, message, &node));
+
+        writeln!(output, "      {{").unwrap();
+        writeln!(output, "        \"index\": {},", index).unwrap();
+        writeln!(output, "        \"message\": \"{}\",", hex::encode(message)).unwrap();
+        let public_key = hex::encode(public_key.to_bytes());
+        writeln!(output, "        \"public_key\": \"{}\",", public_key).unwrap();
+        let wots = hex::encode(signature.signature().to_bytes());
+        writeln!(output, "        \"signature\": \"{}\",", wots).unwrap();
+        let path = signature.path().to_hex().unwrap();
+        writeln!(output, "        \"path\": \"{}\"", path).unwrap();
+        let last = index + 1 == 1 << HEIGHT;
+        writeln!(output, "      }}{}", if last { "" } else { "," }).unwrap();
+    }
+    writeln!(output, "    ]").unwrap();
+    write!(output, "  }}").unwrap();
+}
+
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "vectors.json".to_string());
+
+    let entries = supported_winternitz()
+        .iter()
+        .map(|&w| {
+            let name = format!("sha256_n32_w{}", w);
+            let mut entry = String::new();
+            match w {
+                4 => vectors::<(U32, U32, U3, Sha256, ())>(&name, &mut entry),
+                8 => vectors::<(U32, U32, U7, Sha256, ())>(&name, &mut entry),
+                16 => vectors::<(U32, U32, U15, Sha256, ())>(&name, &mut entry),
+                32 => vectors::<(U32, U32, U31, Sha256, ())>(&name, &mut entry),
+                64 => vectors::<(U32, U32, U63, Sha256, ())>(&name, &mut entry),
+                128 => vectors::<(U32, U32, U127, Sha256, ())>(&name, &mut entry),
+                256 => vectors::<(U32, U32, U255, Sha256, ())>(&name, &mut entry),
+                _ => unreachable!("no parameter set for w {}", w),
+            }
+            entry
+        })
+        .collect::<Vec<_>>();
+    let output = format!("[\n{}\n]\n", entries.join(",\n"));
+
+    fs::write(&path, output).unwrap();
+    println!("written {}", path);
+}
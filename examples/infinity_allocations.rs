@@ -0,0 +1,94 @@
+//! Counts the allocations of deriving many public keys,
+//! building `Message::infinity()` each time versus keeping one
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use digest::{
+    Digest,
+    generic_array::{
+        GenericArray,
+        typenum::{U32, U15},
+    },
+};
+use w_ots::{Message, SecretKey, State};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// xor of the chain input, good enough to count allocations
+#[derive(Clone, Default)]
+struct Xor([u8; 32]);
+
+impl digest::Input for Xor {
+    fn input<B: AsRef<[u8]>>(&mut self, data: B) {
+        for (i, b) in data.as_ref().iter().enumerate() {
+            self.0[i % 32] ^= b;
+        }
+    }
+}
+
+impl digest::Reset for Xor {
+    fn reset(&mut self) {
+        self.0 = [0; 32];
+    }
+}
+
+impl digest::FixedOutput for Xor {
+    type OutputSize = U32;
+
+    fn fixed_result(self) -> GenericArray<u8, U32> {
+        GenericArray::clone_from_slice(&self.0)
+    }
+}
+
+type A = (U32, U32, U15, Xor, ());
+
+const ROUNDS: usize = 1000;
+
+fn count<F>(f: F) -> usize
+where
+    F: FnOnce(),
+{
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn main() {
+    let secret_key = SecretKey::<A>::from_seed(&Xor::digest(b"seed"));
+    let state = State::<A>::new(*secret_key.randomization(), secret_key.data().to_vec());
+
+    let fresh = count(|| {
+        for _ in 0..ROUNDS {
+            drop(&state * Message::infinity());
+        }
+    });
+    let infinity = Message::infinity();
+    let cached = count(|| {
+        for _ in 0..ROUNDS {
+            drop(&state * &infinity);
+        }
+    });
+
+    println!("fresh infinity: {} allocations", fresh);
+    println!("cached infinity: {} allocations", cached);
+    assert!(cached < fresh);
+}
@@ -0,0 +1,180 @@
+use digest::{
+    generic_array::{GenericArray, sequence::GenericSequence, typenum::Unsigned},
+    Digest,
+};
+use byteorder::{ByteOrder, BigEndian};
+
+use super::state::{WOtsPlus, State};
+use super::signature::SecretKey;
+
+type Block<A> = GenericArray<u8, <A as WOtsPlus>::BlockLength>;
+
+/// The 32 byte address of RFC 8391, the layer, the tree, the type
+/// and four type specific words, all big endian, only the one time key type is built here:
+/// the one time key address, the chain address, the hash address and the key and mask
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Address {
+    layer: u32,
+    tree: u64,
+    kind: u32,
+    words: [u32; 4],
+}
+
+impl Address {
+    /// The address of the one time key `ots` of the tree on the layer
+    pub fn ots(layer: u32, tree: u64, ots: u32) -> Self {
+        Address {
+            layer,
+            tree,
+            kind: 0,
+            words: [ots, 0, 0, 0],
+        }
+    }
+
+    pub fn with_chain(self, chain: u32) -> Self {
+        self.with_word(1, chain)
+    }
+
+    pub fn with_hash(self, hash: u32) -> Self {
+        self.with_word(2, hash)
+    }
+
+    pub fn with_key_and_mask(self, key_and_mask: u32) -> Self {
+        self.with_word(3, key_and_mask)
+    }
+
+    fn with_word(mut self, index: usize, value: u32) -> Self {
+        self.words[index] = value;
+        self
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        BigEndian::write_u32(&mut bytes[..4], self.layer);
+        BigEndian::write_u64(&mut bytes[4..12], self.tree);
+        BigEndian::write_u32(&mut bytes[12..16], self.kind);
+        BigEndian::write_u32_into(&self.words, &mut bytes[16..]);
+        bytes
+    }
+}
+
+// `toByte(domain, n)`, the domain separator of the keyed hashes of RFC 8391
+fn domain<A>(value: u8) -> Block<A>
+where
+    A: WOtsPlus,
+{
+    let mut padding = Block::<A>::default();
+    padding[A::BlockLength::USIZE - 1] = value;
+    padding
+}
+
+/// `PRF(key, address) = H(toByte(3, n) || key || address)` of RFC 8391
+pub fn prf_address<A>(key: &Block<A>, address: &Address) -> Block<A>
+where
+    A: WOtsPlus,
+{
+    A::Digest::new()
+        .chain(domain::<A>(3))
+        .chain(key)
+        .chain(address.to_bytes())
+        .result()
+}
+
+/// `PRF_keygen(secret_seed, public_seed || address)
+/// = H(toByte(4, n) || secret_seed || public_seed || address)`, as NIST SP 800-208
+/// fixes the pseudorandom key generation of RFC 8391
+pub fn prf_keygen<A>(secret_seed: &Block<A>, public_seed: &Block<A>, address: &Address) -> Block<A>
+where
+    A: WOtsPlus,
+{
+    A::Digest::new()
+        .chain(domain::<A>(4))
+        .chain(secret_seed)
+        .chain(public_seed)
+        .chain(address.to_bytes())
+        .result()
+}
+
+/// The one time key of the address, the secret chain `i` is `PRF_keygen` of the address
+/// with the chain `i`, so the secret chains match RFC 8391 for the same seeds,
+/// the crate shares the randomization row `j` between the chains, it is `PRF`
+/// of the public seed and the address with the chain 0, the hash `j` and the mask flag,
+/// the chain function differs from RFC 8391, so the public keys do not match it,
+/// `wots_secret_for_index` derives the keys of the signer by it
+pub fn wots_secret_keygen<A>(
+    secret_seed: &Block<A>,
+    public_seed: &Block<A>,
+    address: &Address,
+) -> SecretKey<A>
+where
+    A: WOtsPlus,
+{
+    let address = address.with_hash(0).with_key_and_mask(0);
+    let (l1, l2) = State::<A>::lengths();
+    let data = (0..(l1 + l2))
+        .map(|chain| prf_keygen::<A>(secret_seed, public_seed, &address.with_chain(chain as u32)))
+        .collect();
+    let randomization = GenericArray::generate(|step| {
+        let address = address
+            .with_chain(0)
+            .with_hash(step as u32)
+            .with_key_and_mask(1);
+        prf_address::<A>(public_seed, &address)
+    });
+    SecretKey::new(randomization, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use digest::{
+        generic_array::typenum::{U32, U15},
+        Digest,
+    };
+    use super::super::sha256::Sha256;
+    use super::{Address, prf_address, prf_keygen, wots_secret_keygen};
+
+    type A = (U32, U32, U15, Sha256, ());
+
+    #[test]
+    fn test_address() {
+        let address = Address::ots(1, 0x0203, 4).with_chain(5).with_hash(6);
+        assert_eq!(
+            hex::encode(address.to_bytes()),
+            "00000001000000000000020300000000000000040000000500000006\
+             00000000",
+        );
+
+        // the expected values are computed from the SP 800-208 definitions
+        // with Python `hashlib`, independently of this code
+        let secret_seed = Sha256::digest(b"secret seed");
+        let public_seed = Sha256::digest(b"public seed");
+        let secret_key =
+            wots_secret_keygen::<A>(&secret_seed, &public_seed, &Address::ots(0, 0, 7));
+        assert_eq!(secret_key.data().len(), 67);
+        assert_eq!(
+            hex::encode(secret_key.data()[0]),
+            "725504d16fa79d90c120cb3201de2193116203a3d6c4a72b8a72982d9ff9981a",
+        );
+        assert_eq!(
+            hex::encode(secret_key.data()[66]),
+            "56df11341e9003e69563cf7dc924f4bef37417183d23cf3459a751af59c00cb7",
+        );
+        assert_eq!(
+            hex::encode(secret_key.randomization()[2]),
+            "b2b5bd805661f8223b22c0eacd70e3aafa371060eea5eed10a051687ee10679d",
+        );
+        let chain = Address::ots(0, 0, 7).with_chain(66);
+        assert_eq!(
+            prf_keygen::<A>(&secret_seed, &public_seed, &chain),
+            secret_key.data()[66],
+        );
+        let mask = Address::ots(0, 0, 7).with_hash(2).with_key_and_mask(1);
+        assert_eq!(
+            prf_address::<A>(&public_seed, &mask),
+            secret_key.randomization()[2],
+        );
+
+        let other = wots_secret_keygen::<A>(&secret_seed, &public_seed, &Address::ots(0, 0, 8));
+        assert_ne!(secret_key.to_bytes(), other.to_bytes());
+    }
+}
@@ -0,0 +1,166 @@
+use digest::generic_array::{GenericArray, typenum::Unsigned};
+
+use super::state::WOtsPlus;
+use super::signature::{PublicKey, Signature};
+use super::signer::Signer;
+use super::xmss::XmssOperation;
+use super::error::SignerError;
+
+/// Signatures of the `MessageSize` segments of a long message,
+/// each of them made by its own one time key, the keys are consecutive leaves of the signer
+#[derive(Clone)]
+pub struct AggregateSignature<A>
+where
+    A: WOtsPlus,
+{
+    first_index: u64,
+    signatures: Vec<Signature<A>>,
+}
+
+impl<A> AggregateSignature<A>
+where
+    A: WOtsPlus,
+{
+    /// The leaf of the key of the first segment
+    pub fn first_index(&self) -> u64 {
+        self.first_index
+    }
+
+    pub fn signatures(&self) -> &[Signature<A>] {
+        self.signatures.as_ref()
+    }
+}
+
+/// Split the data into `MessageSize` segments, the data is always padded
+/// by a single `0x80` followed by zeros, so it takes `data.len() / MessageSize + 1` segments
+pub fn segments<A>(data: &[u8]) -> Vec<GenericArray<u8, A::MessageSize>>
+where
+    A: WOtsPlus,
+{
+    let m = A::MessageSize::USIZE;
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    padded.resize(data.len() / m * m + m, 0);
+    padded
+        .chunks(m)
+        .map(GenericArray::clone_from_slice)
+        .collect()
+}
+
+/// Sign each segment with the next unused one time key of the signer, the index advances
+/// past all of them as `sign_batch` does, so the signer must be persisted before
+/// the signature is published, fails and uses nothing if the keys do not all fit
+pub fn sign_aggregate<A, F>(
+    signer: &mut Signer<A>,
+    data: &[u8],
+    f: &F,
+) -> Result<AggregateSignature<A>, SignerError>
+where
+    A: WOtsPlus,
+    F: XmssOperation<GenericArray<u8, A::BlockLength>>,
+{
+    let segments = segments::<A>(data);
+    let first_index = signer.consume(segments.len() as u64, f)?;
+    let signatures = segments
+        .into_iter()
+        .zip(first_index..)
+        .map(|(segment, index)| Signature::sign(&signer.secret_key(index), segment))
+        .collect();
+    Ok(AggregateSignature {
+        first_index,
+        signatures,
+    })
+}
+
+/// The public keys should be given in the order of the segments, they are the leaves
+/// from `first_index`, see `leaves_iter`
+pub fn verify_aggregate<A>(
+    public_keys: &[PublicKey<A>],
+    data: &[u8],
+    signature: &AggregateSignature<A>,
+) -> bool
+where
+    A: WOtsPlus,
+{
+    let segments = segments::<A>(data);
+    segments.len() == public_keys.len()
+        && segments.len() == signature.signatures.len()
+        && segments
+            .into_iter()
+            .zip(public_keys.iter())
+            .zip(signature.signatures.iter())
+            .all(|((segment, public_key), signature)| signature.verify(public_key, segment))
+}
+
+#[cfg(test)]
+mod tests {
+    use digest::{
+        generic_array::{
+            GenericArray,
+            typenum::{U32, U16, U15},
+        },
+        Digest,
+    };
+    use super::super::{
+        sha256::Sha256, SecretKey, PublicKey, HashOperation, Signer, SignerError, leaves_iter,
+    };
+    use super::{segments, sign_aggregate, verify_aggregate};
+
+    type A = (U32, U16, U15, Sha256, ());
+
+    #[test]
+    fn test_aggregate() {
+        let node = HashOperation::<Sha256>::new(b"node");
+        let master_seed = Sha256::digest(b"master seed");
+        let mut signer = Signer::<A>::new(master_seed, 3).unwrap();
+        let root = signer.root(&node);
+        let public_keys = leaves_iter::<A>(&master_seed, signer.public_seed(), 3)
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        let data = [7u8; 40];
+        assert_eq!(segments::<A>(&data).len(), 3);
+        assert_eq!(segments::<A>(&data[..32]).len(), 3);
+        assert_eq!(segments::<A>(&data[..31]).len(), 2);
+
+        let message = |m: &[u8]| GenericArray::clone_from_slice(&Sha256::digest(m)[..16]);
+        signer.sign_next(message(b"before"), &node).unwrap();
+        let signature = sign_aggregate(&mut signer, &data, &node).unwrap();
+        assert_eq!(signature.first_index(), 1);
+        assert_eq!(signature.signatures().len(), 3);
+        assert_eq!(signer.index(), 4);
+        let public_keys = &public_keys[1..];
+        assert!(verify_aggregate(&public_keys[..3], &data, &signature));
+        assert!(!verify_aggregate(&public_keys[1..], &data, &signature));
+        assert!(!verify_aggregate(public_keys, &data, &signature));
+        assert!(!verify_aggregate(
+            &public_keys[..3],
+            &data[..39],
+            &signature
+        ));
+
+        let mut tampered = data;
+        tampered[20] ^= 1;
+        assert!(!verify_aggregate(&public_keys[..3], &tampered, &signature));
+
+        let other = SecretKey::<A>::from_seed(&Sha256::digest(b"other"));
+        let mut wrong = public_keys[..3].to_vec();
+        wrong[1] = PublicKey::from_secret(&other);
+        assert!(!verify_aggregate(&wrong, &data, &signature));
+
+        // the kept path is advanced past the used keys
+        let message = message(b"after");
+        let next = signer.sign_next(message, &node).unwrap();
+        assert_eq!(next.index(), 4);
+        assert!(next.verify(signer.public_seed(), &root, message, &node));
+
+        // 3 keys are left, nothing is used
+        assert_eq!(
+            sign_aggregate(&mut signer, &[0; 48], &node).err(),
+            Some(SignerError::Exhausted { capacity: 8 }),
+        );
+        assert_eq!(signer.index(), 5);
+        assert!(sign_aggregate(&mut signer, &[0; 47], &node).is_ok());
+        assert_eq!(signer.index(), 8);
+    }
+}
@@ -0,0 +1,28 @@
+/// Compare without branching on the content, the time depends only on the lengths
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ct_eq;
+
+    #[test]
+    fn test_ct_eq() {
+        let a = [7u8; 32];
+        assert!(ct_eq(&a, &a));
+        assert!(!ct_eq(&a, &a[1..]));
+        for i in 0..32 {
+            for bit in 0..8 {
+                let mut b = a;
+                b[i] ^= 1 << bit;
+                assert!(!ct_eq(&a, &b));
+            }
+        }
+        assert!(ct_eq(&[], &[]));
+    }
+}
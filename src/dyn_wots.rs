@@ -0,0 +1,256 @@
+use core::marker::PhantomData;
+use digest::{
+    generic_array::{
+        GenericArray,
+        typenum::{Unsigned, U3, U7, U15, U31, U63, U127, U255},
+    },
+    Digest,
+};
+
+use super::state::{WOtsPlus, State, Message};
+use super::signature::{SecretKey, PublicKey, Signature};
+use super::params::{HashId, DigestId};
+use super::error::{DecodeError, ParamError};
+
+/// The Winternitz parameter chosen at runtime, the number of values of a digit
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Winternitz {
+    W4,
+    W8,
+    W16,
+    W32,
+    W64,
+    W128,
+    W256,
+}
+
+impl Winternitz {
+    pub fn from_w(w: usize) -> Option<Self> {
+        match w {
+            4 => Some(Winternitz::W4),
+            8 => Some(Winternitz::W8),
+            16 => Some(Winternitz::W16),
+            32 => Some(Winternitz::W32),
+            64 => Some(Winternitz::W64),
+            128 => Some(Winternitz::W128),
+            256 => Some(Winternitz::W256),
+            _ => None,
+        }
+    }
+
+    pub fn w(&self) -> usize {
+        match *self {
+            Winternitz::W4 => 4,
+            Winternitz::W8 => 8,
+            Winternitz::W16 => 16,
+            Winternitz::W32 => 32,
+            Winternitz::W64 => 64,
+            Winternitz::W128 => 128,
+            Winternitz::W256 => 256,
+        }
+    }
+}
+
+/// The values of `w` the digit decomposition handles, the powers of two from 4 to 256,
+/// those `Winternitz::from_w` accepts
+pub fn supported_winternitz() -> &'static [usize] {
+    &[4, 8, 16, 32, 64, 128, 256]
+}
+
+/// Whether `w`, the number of values of a digit, is one of `supported_winternitz`
+pub fn is_supported(w: usize) -> bool {
+    supported_winternitz().contains(&w)
+}
+
+// calls the generic function with the parameters tuple of the runtime choice,
+// the block and the message are the digest output
+macro_rules! dispatch {
+    ($self:expr, $f:ident($($arg:expr),*)) => {
+        match $self.w {
+            Winternitz::W4 => $f::<(D::OutputSize, D::OutputSize, U3, D, ())>($($arg),*),
+            Winternitz::W8 => $f::<(D::OutputSize, D::OutputSize, U7, D, ())>($($arg),*),
+            Winternitz::W16 => $f::<(D::OutputSize, D::OutputSize, U15, D, ())>($($arg),*),
+            Winternitz::W32 => $f::<(D::OutputSize, D::OutputSize, U31, D, ())>($($arg),*),
+            Winternitz::W64 => $f::<(D::OutputSize, D::OutputSize, U63, D, ())>($($arg),*),
+            Winternitz::W128 => $f::<(D::OutputSize, D::OutputSize, U127, D, ())>($($arg),*),
+            Winternitz::W256 => $f::<(D::OutputSize, D::OutputSize, U255, D, ())>($($arg),*),
+        }
+    };
+}
+
+fn block<A>(bytes: &[u8]) -> Result<GenericArray<u8, A::BlockLength>, DecodeError>
+where
+    A: WOtsPlus,
+{
+    if bytes.len() != A::BlockLength::USIZE {
+        return Err(DecodeError::WrongLength {
+            expected: A::BlockLength::USIZE,
+            got: bytes.len(),
+        });
+    }
+    Ok(GenericArray::clone_from_slice(bytes))
+}
+
+fn generate<A>(seed: &[u8]) -> Result<(Vec<u8>, Vec<u8>), DecodeError>
+where
+    A: WOtsPlus,
+{
+    let secret_key = SecretKey::<A>::from_seed(&block::<A>(seed)?);
+    let public_key = PublicKey::from_secret(&secret_key);
+    Ok((secret_key.to_bytes(), public_key.to_bytes()))
+}
+
+fn sign<A>(secret_key: &[u8], data: &[u8]) -> Result<Vec<u8>, DecodeError>
+where
+    A: WOtsPlus,
+{
+    let secret_key = SecretKey::<A>::from_bytes(secret_key)?;
+    Ok(Signature::sign_message(&secret_key, Message::digest(data)).to_bytes())
+}
+
+fn verify<A>(public_key: &[u8], data: &[u8], signature: &[u8]) -> bool
+where
+    A: WOtsPlus,
+{
+    match (
+        PublicKey::<A>::from_bytes(public_key),
+        Signature::<A>::from_bytes(signature),
+    ) {
+        (Ok(public_key), Ok(signature)) => {
+            signature.verify_message(&public_key, Message::digest(data))
+        },
+        _ => false,
+    }
+}
+
+fn size<A>() -> usize
+where
+    A: WOtsPlus,
+{
+    State::<A>::size()
+}
+
+/// The parameter set chosen at runtime, dispatches to the generic code,
+/// the block and the message size are the output size of the digest `D`,
+/// the crate does not ship the hash functions, so the digest is still a type,
+/// the `hash` the caller records is checked against the identity of `D`,
+/// the keys and the signatures are in the `to_bytes` format, the message is digested
+pub struct DynWots<D>
+where
+    D: Digest + DigestId,
+{
+    w: Winternitz,
+    hash: HashId,
+    phantom_data: PhantomData<D>,
+}
+
+impl<D> DynWots<D>
+where
+    D: Digest + DigestId,
+{
+    /// Fails if `hash` is not the identity of the digest `D`
+    pub fn new(w: Winternitz, hash: HashId) -> Result<Self, ParamError> {
+        if hash != D::HASH_ID {
+            return Err(ParamError::HashMismatch {
+                expected: D::HASH_ID,
+                got: hash,
+            });
+        }
+        Ok(DynWots {
+            w,
+            hash,
+            phantom_data: PhantomData,
+        })
+    }
+
+    pub fn w(&self) -> Winternitz {
+        self.w
+    }
+
+    pub fn hash(&self) -> HashId {
+        self.hash
+    }
+
+    /// The size of the secret key, the public key and the signature
+    pub fn size(&self) -> usize {
+        dispatch!(self, size())
+    }
+
+    /// The secret key and the public key from the seed of the output size of `D`
+    pub fn generate(&self, seed: &[u8]) -> Result<(Vec<u8>, Vec<u8>), DecodeError> {
+        dispatch!(self, generate(seed))
+    }
+
+    pub fn sign(&self, secret_key: &[u8], data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        dispatch!(self, sign(secret_key, data))
+    }
+
+    pub fn verify(&self, public_key: &[u8], data: &[u8], signature: &[u8]) -> bool {
+        dispatch!(self, verify(public_key, data, signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use digest::{
+        generic_array::typenum::{U32, U15},
+        Digest,
+    };
+    use super::super::{
+        sha256::Sha256, state::validate_winternitz, HashId, SecretKey, PublicKey, Signature,
+        Message, ParamError,
+    };
+    use super::{Winternitz, supported_winternitz, is_supported, DynWots};
+
+    #[test]
+    fn test_dyn_wots() {
+        let seed = Sha256::digest(b"seed");
+        for &w in &[4, 16, 256] {
+            let w = Winternitz::from_w(w).unwrap();
+            let scheme = DynWots::<Sha256>::new(w, HashId::Sha256).unwrap();
+            let (secret_key, public_key) = scheme.generate(&seed).unwrap();
+            assert_eq!(secret_key.len(), scheme.size());
+            assert_eq!(public_key.len(), scheme.size());
+
+            let signature = scheme.sign(&secret_key, b"data").unwrap();
+            assert!(scheme.verify(&public_key, b"data", &signature));
+            assert!(!scheme.verify(&public_key, b"other", &signature));
+            assert!(!scheme.verify(&public_key[1..], b"data", &signature));
+        }
+
+        // the same as the generic code
+        type A = (U32, U32, U15, Sha256, ());
+        let scheme = DynWots::<Sha256>::new(Winternitz::W16, HashId::Sha256).unwrap();
+        let (secret_key, public_key) = scheme.generate(&seed).unwrap();
+        let generic = SecretKey::<A>::from_seed(&seed);
+        assert_eq!(public_key, PublicKey::from_secret(&generic).to_bytes());
+        assert_eq!(
+            scheme.sign(&secret_key, b"data").unwrap(),
+            Signature::sign_message(&generic, Message::digest(b"data")).to_bytes(),
+        );
+
+        let other = DynWots::<Sha256>::new(Winternitz::W256, HashId::Sha256).unwrap();
+        assert!(other.sign(&secret_key, b"data").is_err());
+        assert_eq!(
+            DynWots::<Sha256>::new(Winternitz::W16, HashId::Sha3_256).err(),
+            Some(ParamError::HashMismatch {
+                expected: HashId::Sha256,
+                got: HashId::Sha3_256,
+            }),
+        );
+        assert_eq!(Winternitz::from_w(2), None);
+        assert_eq!(Winternitz::W128.w(), 128);
+    }
+
+    #[test]
+    fn test_supported_winternitz() {
+        for w in 1..=1024 {
+            assert_eq!(is_supported(w), validate_winternitz(w - 1).is_ok());
+            assert_eq!(is_supported(w), Winternitz::from_w(w).is_some());
+        }
+        for &w in supported_winternitz() {
+            assert_eq!(Winternitz::from_w(w).map(|w| w.w()), Some(w));
+        }
+        assert!(!is_supported(0));
+    }
+}
@@ -0,0 +1,223 @@
+use core::fmt;
+
+use super::params::{ParamHeader, HashId};
+use super::xmss::MAX_HEIGHT;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DecodeError {
+    WrongLength {
+        expected: usize,
+        got: usize,
+    },
+    WrongPublicKeyLength {
+        expected: usize,
+        got: usize,
+    },
+    ParamMismatch {
+        expected: ParamHeader,
+        got: ParamHeader,
+    },
+    UnknownRandomizationFlag {
+        flag: u8,
+    },
+    MalformedSignature {
+        expected_randomization: usize,
+        expected_chains: usize,
+        got: usize,
+    },
+    InvalidHex {
+        position: usize,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::WrongLength {
+                expected: expected,
+                got: got,
+            } => write!(f, "wrong length, expected: {}, got: {}", expected, got),
+            DecodeError::WrongPublicKeyLength {
+                expected: expected,
+                got: got,
+            } => write!(
+                f,
+                "wrong public key length, expected: {}, got: {}",
+                expected, got,
+            ),
+            DecodeError::ParamMismatch {
+                expected: expected,
+                got: got,
+            } => write!(f, "wrong parameters, expected: {}, got: {}", expected, got),
+            DecodeError::UnknownRandomizationFlag { flag: flag } => {
+                write!(f, "unknown randomization flag: {}", flag)
+            },
+            DecodeError::MalformedSignature {
+                expected_randomization: randomization,
+                expected_chains: chains,
+                got: got,
+            } => write!(
+                f,
+                "malformed signature, expected: {} bytes of randomization and {} bytes of chains, \
+                 got: {} bytes",
+                randomization, chains, got,
+            ),
+            DecodeError::InvalidHex { position: position } => {
+                write!(f, "invalid hex at the position {}", position)
+            },
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParamError {
+    ZeroBlockLength,
+    ZeroMessageSize,
+    WinternitzNotPowerOfTwoMinusOne { winternitz_minus_one: usize },
+    UnsupportedWinternitz { winternitz_minus_one: usize },
+    HashMismatch { expected: HashId, got: HashId },
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            &ParamError::ZeroBlockLength => write!(f, "`BlockLength` should not be zero"),
+            &ParamError::ZeroMessageSize => write!(f, "`MessageSize` should not be zero"),
+            ParamError::WinternitzNotPowerOfTwoMinusOne {
+                winternitz_minus_one: w,
+            } => write!(
+                f,
+                "`WinternitzMinusOne` should be a power of two minus one, got: {}",
+                w,
+            ),
+            ParamError::UnsupportedWinternitz {
+                winternitz_minus_one: w,
+            } => write!(f, "`WinternitzMinusOne` {} is not supported", w),
+            ParamError::HashMismatch {
+                expected: expected,
+                got: got,
+            } => write!(
+                f,
+                "the digest is {:?}, but the parameters name {:?}",
+                expected, got,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MessageError {
+    ChainCountMismatch { data: usize, ranges: usize },
+    DigitOutOfRange { chain: usize, value: usize },
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::ChainCountMismatch {
+                data: data,
+                ranges: ranges,
+            } => write!(
+                f,
+                "the message has {} digits, but the state has {} chains",
+                ranges, data,
+            ),
+            MessageError::DigitOutOfRange {
+                chain: chain,
+                value: value,
+            } => write!(
+                f,
+                "the digit {} of the chain {} exceeds `WinternitzMinusOne`",
+                value, chain,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum XmssError {
+    HeightMismatch { expected: usize, got: usize },
+    IndexMismatch { expected: u64, got: u64 },
+    MissingLevel,
+    BadHeight { height: usize },
+}
+
+impl fmt::Display for XmssError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmssError::HeightMismatch {
+                expected: expected,
+                got: got,
+            } => write!(f, "wrong path height, expected: {}, got: {}", expected, got),
+            XmssError::IndexMismatch {
+                expected: expected,
+                got: got,
+            } => write!(f, "wrong path index, expected: {}, got: {}", expected, got),
+            &XmssError::MissingLevel => write!(f, "the path has no sibling on some level"),
+            XmssError::BadHeight { height: height } => write!(
+                f,
+                "the height should be at most {}, got: {}",
+                MAX_HEIGHT, height,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for XmssError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SignerError {
+    Decode(DecodeError),
+    BadHeight { height: usize },
+    BadIndex { index: u64, capacity: u64 },
+    IndexRollback { index: u64, last_known_index: u64 },
+    Exhausted { capacity: u64 },
+    RootMismatch,
+}
+
+impl From<DecodeError> for SignerError {
+    fn from(v: DecodeError) -> Self {
+        SignerError::Decode(v)
+    }
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignerError::Decode(error) => write!(f, "{}", error),
+            SignerError::BadHeight { height: height } => {
+                write!(
+                    f,
+                    "the height should be at most {}, got: {}",
+                    MAX_HEIGHT, height
+                )
+            },
+            SignerError::BadIndex {
+                index: index,
+                capacity: capacity,
+            } => write!(f, "the index {} exceeds the capacity {}", index, capacity),
+            SignerError::IndexRollback {
+                index: index,
+                last_known_index: last_known_index,
+            } => write!(
+                f,
+                "the stored index {} is lower than the last known index {}, the state is stale",
+                index, last_known_index,
+            ),
+            SignerError::Exhausted { capacity: capacity } => {
+                write!(f, "all {} one time keys are used", capacity)
+            },
+            &SignerError::RootMismatch => {
+                write!(f, "the signer does not produce the root of the public key")
+            },
+        }
+    }
+}
+
+impl std::error::Error for SignerError {}
@@ -0,0 +1,3 @@
+//! Conversions to the layouts of other implementations, for differential testing
+
+pub mod sphincs;
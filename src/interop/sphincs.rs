@@ -0,0 +1,354 @@
+//! The layout of the WOTS keys in the SPHINCS+ reference implementation,
+//! the `len` chains of `n` bytes concatenated without the randomization.
+//!
+//! The layout conversion keeps the chain function of this crate, `H(r_i ^ x)` with
+//! the randomization, so the randomization is given separately and the tips differ
+//! from the ones of the reference. For the differential testing the SHA-2 chain mode,
+//! `sphincs_sha2_*`, computes the secret chains, the public key and the signature
+//! as the reference does, the hash keyed by the public seed and addressed by the position,
+//! FIPS 205 (SLH-DSA) with SHA-256, `n` 32 and `w` 16, the digits are of `Message`.
+
+use digest::{
+    generic_array::{GenericArray, typenum::Unsigned},
+    Digest,
+};
+use byteorder::{ByteOrder, BigEndian};
+
+use super::super::{
+    state::{WOtsPlus, State, Message},
+    signature::{SecretKey, PublicKey},
+    error::DecodeError,
+};
+
+type Block<A> = GenericArray<u8, <A as WOtsPlus>::BlockLength>;
+
+fn chains_len<A>() -> usize
+where
+    A: WOtsPlus,
+{
+    let (l1, l2) = State::<A>::lengths();
+    (l1 + l2) * A::BlockLength::USIZE
+}
+
+fn chains<A>(bytes: &[u8]) -> Result<Vec<GenericArray<u8, A::BlockLength>>, DecodeError>
+where
+    A: WOtsPlus,
+{
+    if bytes.len() != chains_len::<A>() {
+        return Err(DecodeError::WrongLength {
+            expected: chains_len::<A>(),
+            got: bytes.len(),
+        });
+    }
+    Ok(bytes
+        .chunks(A::BlockLength::USIZE)
+        .map(GenericArray::clone_from_slice)
+        .collect())
+}
+
+fn concat<A>(blocks: &[GenericArray<u8, A::BlockLength>]) -> Vec<u8>
+where
+    A: WOtsPlus,
+{
+    let mut bytes = Vec::with_capacity(chains_len::<A>());
+    blocks
+        .iter()
+        .for_each(|block| bytes.extend_from_slice(block.as_ref()));
+    bytes
+}
+
+/// The secret key from the concatenated secret chains of the reference
+pub fn from_sphincs_wots_sk<A>(
+    bytes: &[u8],
+    randomization: GenericArray<GenericArray<u8, A::BlockLength>, A::WinternitzMinusOne>,
+) -> Result<SecretKey<A>, DecodeError>
+where
+    A: WOtsPlus,
+{
+    chains::<A>(bytes).map(|data| SecretKey::new(randomization, data))
+}
+
+pub fn to_sphincs_wots_sk<A>(secret_key: &SecretKey<A>) -> Vec<u8>
+where
+    A: WOtsPlus,
+{
+    concat::<A>(secret_key.data())
+}
+
+/// The concatenated tips, the public key of the reference before it is compressed
+pub fn to_sphincs_wots_pk<A>(public_key: &PublicKey<A>) -> Vec<u8>
+where
+    A: WOtsPlus,
+{
+    concat::<A>(public_key.tips())
+}
+
+pub fn from_sphincs_wots_pk<A>(
+    bytes: &[u8],
+    randomization: &GenericArray<GenericArray<u8, A::BlockLength>, A::WinternitzMinusOne>,
+) -> Result<PublicKey<A>, DecodeError>
+where
+    A: WOtsPlus,
+{
+    chains::<A>(bytes)?;
+    let mut state = Vec::with_capacity(State::<A>::size());
+    randomization
+        .iter()
+        .for_each(|block| state.extend_from_slice(block.as_ref()));
+    state.extend_from_slice(bytes);
+    PublicKey::from_bytes(&state)
+}
+
+/// The address of the hash calls of a WOTS key pair in the SPHINCS+ hypertree,
+/// the layer, the tree in it and the key pair in the tree
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SphincsAddress {
+    pub layer: u32,
+    pub tree: u64,
+    pub keypair: u32,
+}
+
+// the types of the address, the chain step and the secret derivation
+const WOTS_HASH: u8 = 0;
+const WOTS_PRF: u8 = 5;
+
+impl SphincsAddress {
+    // the compressed address of the SHA-2 sets, the layer byte, the lower 8 bytes of
+    // the tree, the type byte, the key pair, the chain and the hash as big endian `u32`
+    fn compressed(&self, kind: u8, chain: usize, hash: usize) -> [u8; 22] {
+        let mut bytes = [0; 22];
+        bytes[0] = self.layer as u8;
+        BigEndian::write_u64(&mut bytes[1..9], self.tree);
+        bytes[9] = kind;
+        BigEndian::write_u32(&mut bytes[10..14], self.keypair);
+        BigEndian::write_u32(&mut bytes[14..18], chain as u32);
+        BigEndian::write_u32(&mut bytes[18..22], hash as u32);
+        bytes
+    }
+}
+
+// `H(pk_seed || 0 ^ (64 - n) || address || input)`, the public seed is padded
+// to the block of SHA-256, it is `F` and `PRF` of the SHA-2 sets
+fn sha2_hash<A>(pk_seed: &Block<A>, address: [u8; 22], input: &[u8]) -> Block<A>
+where
+    A: WOtsPlus,
+{
+    A::Digest::new()
+        .chain(pk_seed)
+        .chain(vec![0; 64 - A::BlockLength::USIZE])
+        .chain(address)
+        .chain(input)
+        .result()
+}
+
+// the steps `start..(start + steps)` of the chain, each addressed by its position
+fn sha2_chain<A>(
+    pk_seed: &Block<A>,
+    address: &SphincsAddress,
+    chain: usize,
+    x: Block<A>,
+    start: usize,
+    steps: usize,
+) -> Block<A>
+where
+    A: WOtsPlus,
+{
+    (start..(start + steps)).fold(x, |x, hash| {
+        let address = address.compressed(WOTS_HASH, chain, hash);
+        sha2_hash::<A>(pk_seed, address, x.as_ref())
+    })
+}
+
+// advance each chain by the steps the digits give, `digit..(digit + steps(digit))`
+fn sha2_chains<A, S>(
+    bytes: &[u8],
+    pk_seed: &Block<A>,
+    address: &SphincsAddress,
+    digits: &[usize],
+    range: S,
+) -> Result<Vec<u8>, DecodeError>
+where
+    A: WOtsPlus,
+    S: Fn(usize) -> (usize, usize),
+{
+    let blocks = chains::<A>(bytes)?
+        .into_iter()
+        .zip(digits)
+        .enumerate()
+        .map(|(chain, (x, &digit))| {
+            let (start, steps) = range(digit);
+            sha2_chain::<A>(pk_seed, address, chain, x, start, steps)
+        })
+        .collect::<Vec<_>>();
+    Ok(concat::<A>(&blocks))
+}
+
+/// The secret chains of the reference, `PRF(pk_seed, sk_seed, address)` of each chain,
+/// in the layout of `to_sphincs_wots_sk`
+pub fn sphincs_sha2_sk<A>(
+    sk_seed: &Block<A>,
+    pk_seed: &Block<A>,
+    address: &SphincsAddress,
+) -> Vec<u8>
+where
+    A: WOtsPlus,
+{
+    let (l1, l2) = State::<A>::lengths();
+    let blocks = (0..(l1 + l2))
+        .map(|chain| {
+            let address = address.compressed(WOTS_PRF, chain, 0);
+            sha2_hash::<A>(pk_seed, address, sk_seed.as_ref())
+        })
+        .collect::<Vec<_>>();
+    concat::<A>(&blocks)
+}
+
+/// The tips of the reference before they are compressed, each chain advanced `w - 1` steps
+pub fn sphincs_sha2_pk<A>(
+    sk: &[u8],
+    pk_seed: &Block<A>,
+    address: &SphincsAddress,
+) -> Result<Vec<u8>, DecodeError>
+where
+    A: WOtsPlus,
+{
+    let w = A::WinternitzMinusOne::USIZE;
+    let (l1, l2) = State::<A>::lengths();
+    sha2_chains::<A, _>(sk, pk_seed, address, &vec![0; l1 + l2], |_| (0, w))
+}
+
+/// The signature of the reference, each chain advanced by its digit of `Message::message`,
+/// the digits and the checksum of `w` 16 are the ones of the reference
+pub fn sphincs_sha2_sign<A>(
+    sk: &[u8],
+    pk_seed: &Block<A>,
+    address: &SphincsAddress,
+    message: GenericArray<u8, A::MessageSize>,
+) -> Result<Vec<u8>, DecodeError>
+where
+    A: WOtsPlus,
+{
+    let digits = Message::<A>::message(message).explain();
+    sha2_chains::<A, _>(sk, pk_seed, address, &digits, |digit| (0, digit))
+}
+
+/// The tips recovered from the signature of the reference, the verifier compares
+/// them with `sphincs_sha2_pk`
+pub fn sphincs_sha2_pk_from_sig<A>(
+    signature: &[u8],
+    pk_seed: &Block<A>,
+    address: &SphincsAddress,
+    message: GenericArray<u8, A::MessageSize>,
+) -> Result<Vec<u8>, DecodeError>
+where
+    A: WOtsPlus,
+{
+    let w = A::WinternitzMinusOne::USIZE;
+    let digits = Message::<A>::message(message).explain();
+    sha2_chains::<A, _>(signature, pk_seed, address, &digits, |digit| {
+        (digit, w - digit)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use digest::{
+        generic_array::typenum::{U32, U15},
+        Digest,
+    };
+    use super::super::super::{sha256::Sha256, Signature, SecretKey, PublicKey, DecodeError};
+    use super::{
+        to_sphincs_wots_sk, to_sphincs_wots_pk, from_sphincs_wots_sk, from_sphincs_wots_pk,
+        sphincs_sha2_sk, sphincs_sha2_pk, sphincs_sha2_sign, sphincs_sha2_pk_from_sig,
+        SphincsAddress,
+    };
+
+    type A = (U32, U32, U15, Sha256, ());
+
+    #[test]
+    fn test_sphincs_layout() {
+        let secret_key = SecretKey::<A>::from_seed(&Sha256::digest(b"seed"));
+        let public_key = PublicKey::from_secret(&secret_key);
+
+        let sk = to_sphincs_wots_sk(&secret_key);
+        let pk = to_sphincs_wots_pk(&public_key);
+        assert_eq!(sk.len(), 67 * 32);
+        assert_eq!(pk.len(), 67 * 32);
+        assert_eq!(&pk[32..64], &public_key.tips()[1][..]);
+
+        let randomization = *secret_key.randomization();
+        let imported = from_sphincs_wots_sk::<A>(&sk, randomization).unwrap();
+        let imported_public_key = from_sphincs_wots_pk::<A>(&pk, &randomization).unwrap();
+        assert_eq!(imported.data(), secret_key.data());
+        assert_eq!(imported_public_key.to_bytes(), public_key.to_bytes());
+        assert_eq!(PublicKey::from_secret(&imported).tips(), public_key.tips());
+
+        let message = Sha256::digest(b"message");
+        assert!(Signature::sign(&imported, message).verify(&imported_public_key, message));
+
+        assert_eq!(
+            from_sphincs_wots_pk::<A>(&pk[1..], &randomization).err(),
+            Some(DecodeError::WrongLength {
+                expected: 67 * 32,
+                got: 67 * 32 - 1,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_sphincs_sha2() {
+        // SLH-DSA of FIPS 205, the SHA-2 `F` and `PRF` with `n` 32 and `w` 16, the expected
+        // values are computed from the FIPS 205 definitions with Python `hashlib`
+        let sk_seed = Sha256::digest(b"sk seed");
+        let pk_seed = Sha256::digest(b"pk seed");
+        let address = SphincsAddress {
+            layer: 1,
+            tree: 0x0102030405060708,
+            keypair: 7,
+        };
+        let sk = sphincs_sha2_sk::<A>(&sk_seed, &pk_seed, &address);
+        assert_eq!(
+            hex::encode(&sk[..32]),
+            "c121caa47a0083a7344ee60195978a2d2a044997364641a935a70a77aea5c098",
+        );
+        let pk = sphincs_sha2_pk::<A>(&sk, &pk_seed, &address).unwrap();
+        assert_eq!(
+            hex::encode(&pk[..32]),
+            "ccd25fac5f599e6ac2984a18dc4d10756ee759422d400bdaa551fe6f4d091322",
+        );
+        assert_eq!(
+            hex::encode(Sha256::digest(&pk)),
+            "cb42481c9b68c14ff36c9025794e6e12cf2305d13f74ee1698121eef47fa5820",
+        );
+
+        let message = Sha256::digest(b"message");
+        let signature = sphincs_sha2_sign::<A>(&sk, &pk_seed, &address, message).unwrap();
+        assert_eq!(
+            hex::encode(Sha256::digest(&signature)),
+            "fd1c494c817a04c4b9fa8830814284a9da49e67f5752420c80a723a39ceaae41",
+        );
+        assert_eq!(
+            hex::encode(&signature[66 * 32..]),
+            "772c595133abe0b97631ef72620ea796895826badc3f8a654e099d50caf0b7ad",
+        );
+        assert_eq!(
+            sphincs_sha2_pk_from_sig::<A>(&signature, &pk_seed, &address, message).unwrap(),
+            pk,
+        );
+        let other = Sha256::digest(b"other");
+        assert_ne!(
+            sphincs_sha2_pk_from_sig::<A>(&signature, &pk_seed, &address, other).unwrap(),
+            pk,
+        );
+        let moved = SphincsAddress {
+            keypair: 8,
+            ..address
+        };
+        assert_ne!(sphincs_sha2_pk::<A>(&sk, &pk_seed, &moved).unwrap(), pk);
+
+        // the secret chains are the ones of the layout conversion
+        let imported = from_sphincs_wots_sk::<A>(&sk, Default::default()).unwrap();
+        assert_eq!(to_sphincs_wots_sk(&imported), sk);
+        assert!(sphincs_sha2_pk::<A>(&sk[1..], &pk_seed, &address).is_err());
+    }
+}
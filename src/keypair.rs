@@ -0,0 +1,606 @@
+use digest::{
+    generic_array::{GenericArray, typenum::Unsigned},
+    Digest,
+};
+use byteorder::{ByteOrder, BigEndian};
+
+use super::state::WOtsPlus;
+use super::error::{DecodeError, SignerError};
+use super::xmss::{XmssOperation, Treehash};
+use super::xmss_signature::XmssSignature;
+use super::signer::Signer;
+use super::seed::{self, derive, Domain, leaf_public_key};
+
+type Block<A> = GenericArray<u8, <A as WOtsPlus>::BlockLength>;
+
+/// The secret of the xmss key pair is the signer at the index 0
+pub type XmssSecret<A> = Signer<A>;
+
+/// The root of the tree and the public seed, which is derived from the master seed,
+/// identifies the key and binds each one time key to its leaf,
+/// it reveals nothing about the master seed
+pub struct XmssPublic<A>
+where
+    A: WOtsPlus,
+{
+    root: Block<A>,
+    public_seed: Block<A>,
+}
+
+impl<A> Clone for XmssPublic<A>
+where
+    A: WOtsPlus,
+{
+    fn clone(&self) -> Self {
+        XmssPublic {
+            root: self.root.clone(),
+            public_seed: self.public_seed.clone(),
+        }
+    }
+}
+
+impl<A> XmssPublic<A>
+where
+    A: WOtsPlus,
+{
+    pub fn root(&self) -> &Block<A> {
+        &self.root
+    }
+
+    pub fn public_seed(&self) -> &Block<A> {
+        &self.public_seed
+    }
+
+    /// The public seed followed by the root, `2 * BlockLength`
+    pub const fn size() -> usize {
+        2 * A::BlockLength::USIZE
+    }
+
+    /// The public seed followed by the root, it is what gets published
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::size());
+        bytes.extend_from_slice(&self.public_seed);
+        bytes.extend_from_slice(&self.root);
+        bytes
+    }
+
+    /// The length should be exactly `size`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() != Self::size() {
+            return Err(DecodeError::WrongLength {
+                expected: Self::size(),
+                got: bytes.len(),
+            });
+        }
+        let (public_seed, root) = bytes.split_at(A::BlockLength::USIZE);
+        Ok(XmssPublic {
+            root: GenericArray::clone_from_slice(root),
+            public_seed: GenericArray::clone_from_slice(public_seed),
+        })
+    }
+
+    /// `H("w-ots public key" || public_seed || root)`, the single block identifying the key,
+    /// e.g. to anchor it on chain while the full public key is stored elsewhere
+    pub fn commitment(&self) -> Block<A> {
+        A::Digest::new()
+            .chain(b"w-ots public key")
+            .chain(&self.public_seed)
+            .chain(&self.root)
+            .result()
+    }
+
+    /// The one time key is bound to its leaf by the public seed, see
+    /// `XmssSignature::verify`, so the key of another public seed
+    /// and the same root does not verify the signature
+    pub fn verify<F>(
+        &self,
+        signature: &XmssSignature<A, Block<A>>,
+        message: GenericArray<u8, A::MessageSize>,
+        f: &F,
+    ) -> bool
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        signature.verify(&self.public_seed, &self.root, message, f)
+    }
+}
+
+/// Generation of the whole xmss key pair from the master seed
+pub enum XmssKeyPair {}
+
+impl XmssKeyPair {
+    /// Same as `generate`, but after each `every` leaves the checkpoint gets the progress
+    /// to persist it, and the generation stops if it returns `false`, see `resume`,
+    /// the error is the height above `MAX_HEIGHT`
+    pub fn generate_resumable<A, F, C>(
+        master_seed: Block<A>,
+        height: usize,
+        every: u64,
+        f: &F,
+        checkpoint: C,
+    ) -> Result<KeyGenStep<A>, SignerError>
+    where
+        A: WOtsPlus,
+        F: XmssOperation<Block<A>>,
+        C: FnMut(&KeyGenProgress<A>) -> bool,
+    {
+        Ok(Self::resume(
+            master_seed,
+            KeyGenProgress::new(height)?,
+            every,
+            f,
+            checkpoint,
+        ))
+    }
+
+    /// Continue the generation from the progress with the same master seed,
+    /// the stopped generation gives back the progress
+    pub fn resume<A, F, C>(
+        master_seed: Block<A>,
+        progress: KeyGenProgress<A>,
+        every: u64,
+        f: &F,
+        checkpoint: C,
+    ) -> KeyGenStep<A>
+    where
+        A: WOtsPlus,
+        F: XmssOperation<Block<A>>,
+        C: FnMut(&KeyGenProgress<A>) -> bool,
+    {
+        assert!(every > 0);
+        let mut progress = progress;
+        let mut checkpoint = checkpoint;
+        let public_seed = derive::<A>(&master_seed, Domain::PublicSeed, 0);
+        while !progress.is_complete() {
+            progress.step(&master_seed, &public_seed, f);
+            if progress.done.is_multiple_of(every)
+                && !progress.is_complete()
+                && !checkpoint(&progress)
+            {
+                return KeyGenStep::Paused(progress);
+            }
+        }
+
+        let root = progress.treehash.finish(f).unwrap();
+        KeyGenStep::Done(
+            Signer::new(master_seed, progress.height)
+                .expect("the height of the progress is checked"),
+            XmssPublic { root, public_seed },
+        )
+    }
+
+    /// Derive all one time keys, compress them into the leaves and collapse the tree,
+    /// it takes `2 ^ height` key generations, fails on the height above `MAX_HEIGHT`
+    pub fn generate<A, F>(
+        master_seed: Block<A>,
+        height: usize,
+        f: &F,
+    ) -> Result<(XmssSecret<A>, XmssPublic<A>), SignerError>
+    where
+        A: WOtsPlus,
+        F: XmssOperation<Block<A>>,
+    {
+        let secret = Signer::new(master_seed, height)?;
+        let public = XmssPublic {
+            root: secret.root(f),
+            public_seed: secret.public_seed().clone(),
+        };
+        Ok((secret, public))
+    }
+
+    /// Same as `generate`, the master seed is `H("w-ots mnemonic" || seed)`,
+    /// the seed is the one BIP39 derives from the mnemonic and the passphrase,
+    /// the derivation of the seed itself is left to the wallet
+    #[cfg(feature = "mnemonic")]
+    pub fn from_mnemonic_seed<A, F>(
+        seed: &[u8],
+        height: usize,
+        f: &F,
+    ) -> Result<(XmssSecret<A>, XmssPublic<A>), SignerError>
+    where
+        A: WOtsPlus,
+        F: XmssOperation<Block<A>>,
+    {
+        let master_seed = A::Digest::new()
+            .chain(b"w-ots mnemonic")
+            .chain(seed)
+            .result();
+        Self::generate(master_seed, height, f)
+    }
+}
+
+/// The result of the resumable generation, the key pair, or the progress
+/// if the checkpoint stopped the generation
+pub enum KeyGenStep<A>
+where
+    A: WOtsPlus,
+{
+    Done(XmssSecret<A>, XmssPublic<A>),
+    Paused(KeyGenProgress<A>),
+}
+
+/// The state of an interrupted key generation, the number of leaves done and the treehash
+/// frontier, the root of each complete subtree not yet joined, one per bit of the number,
+/// it does not store the master seed, `resume` should get the same one
+pub struct KeyGenProgress<A>
+where
+    A: WOtsPlus,
+{
+    height: usize,
+    done: u64,
+    treehash: Treehash<Block<A>>,
+}
+
+impl<A> KeyGenProgress<A>
+where
+    A: WOtsPlus,
+{
+    /// Fails on the height above `MAX_HEIGHT`
+    pub fn new(height: usize) -> Result<Self, SignerError> {
+        seed::check_height(height)?;
+        Ok(KeyGenProgress {
+            height,
+            done: 0,
+            treehash: Treehash::from_stack(Vec::with_capacity(height + 1)),
+        })
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The number of leaves done
+    pub fn done(&self) -> u64 {
+        self.done
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.done == 1 << self.height
+    }
+
+    fn step<F>(&mut self, master_seed: &Block<A>, public_seed: &Block<A>, f: &F)
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        let leaf = leaf_public_key::<A, F>(master_seed, public_seed, self.done, f);
+        self.treehash.push(leaf, f);
+        self.done += 1;
+    }
+
+    /// The height as a byte, the number of leaves done as big endian `u64`
+    /// and the frontier, from the highest subtree
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.height as u8];
+        bytes.extend_from_slice(&self.done.to_be_bytes());
+        self.treehash
+            .stack()
+            .iter()
+            .for_each(|(_, node)| bytes.extend_from_slice(node.as_ref()));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SignerError> {
+        let n = A::BlockLength::USIZE;
+        if bytes.len() < 9 {
+            return Err(SignerError::Decode(DecodeError::WrongLength {
+                expected: 9,
+                got: bytes.len(),
+            }));
+        }
+        let height = bytes[0] as usize;
+        seed::check_height(height)?;
+        let done = BigEndian::read_u64(&bytes[1..9]);
+        if done > 1 << height {
+            return Err(SignerError::BadIndex {
+                index: done,
+                capacity: 1 << height,
+            });
+        }
+        // the subtree of each bit of the number, the highest first
+        let heights = (0..=height)
+            .rev()
+            .filter(|&h| (done >> h) & 1 == 1)
+            .collect::<Vec<_>>();
+        let expected = 9 + heights.len() * n;
+        if bytes.len() != expected {
+            return Err(SignerError::Decode(DecodeError::WrongLength {
+                expected,
+                got: bytes.len(),
+            }));
+        }
+        let stack = heights
+            .into_iter()
+            .zip(bytes[9..].chunks(n).map(GenericArray::clone_from_slice))
+            .collect();
+        Ok(KeyGenProgress {
+            height,
+            done,
+            treehash: Treehash::from_stack(stack),
+        })
+    }
+}
+
+/// Rebuild the whole tree from the master seed and return its root, to compare it with
+/// the published one, each leaf is bound by the public seed, see `leaf_public_key`,
+/// so the wrong public seed gives another root, fails on the height above `MAX_HEIGHT`,
+/// with the `parallel` feature the leaves are derived in several threads
+pub fn audit_tree<A, F>(
+    master_seed: &Block<A>,
+    public_seed: &Block<A>,
+    height: usize,
+    f: &F,
+) -> Result<Block<A>, SignerError>
+where
+    A: WOtsPlus,
+    F: XmssOperation<Block<A>> + Sync,
+{
+    Ok(seed::leaves_tree::<A, F>(master_seed, public_seed, height, f)?.collapse(f))
+}
+
+/// Build the root leaf by leaf, each leaf is derived by `leaf_public_key` as the signer
+/// does and merged into the treehash stack, it holds at most one node per height,
+/// the root is the one of `XmssTree::collapse`, of `Signer::root` for the full tree
+pub struct LeafStream<A, F>
+where
+    A: WOtsPlus,
+    F: XmssOperation<Block<A>>,
+{
+    master_seed: Block<A>,
+    public_seed: Block<A>,
+    f: F,
+    count: u64,
+    treehash: Treehash<Block<A>>,
+}
+
+impl<A, F> LeafStream<A, F>
+where
+    A: WOtsPlus,
+    F: XmssOperation<Block<A>>,
+{
+    pub fn new(master_seed: Block<A>, public_seed: Block<A>, f: F) -> Self {
+        LeafStream {
+            master_seed,
+            public_seed,
+            f,
+            count: 0,
+            treehash: Treehash::new(),
+        }
+    }
+
+    /// The number of leaves pushed
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Derive the leaf `count` and push it
+    pub fn push(&mut self) {
+        let leaf =
+            leaf_public_key::<A, F>(&self.master_seed, &self.public_seed, self.count, &self.f);
+        self.treehash.push(leaf, &self.f);
+        self.count += 1;
+    }
+
+    /// The root, `None` if nothing is pushed, the incomplete subtrees are merged
+    /// from the lowest, the odd node is promoted as `collapse` does
+    pub fn finish(self) -> Option<Block<A>> {
+        self.treehash.finish(&self.f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use digest::{
+        generic_array::typenum::{U32, U15},
+        Digest,
+    };
+    use super::super::{
+        sha256::Sha256, HashOperation, XmssTree, XmssSignature, DecodeError, SignerError, Signer,
+        leaves_tree,
+    };
+    use super::{XmssPublic, XmssKeyPair, KeyGenStep, KeyGenProgress, audit_tree, LeafStream};
+
+    type A = (U32, U32, U15, Sha256, ());
+
+    fn node() -> HashOperation<Sha256> {
+        HashOperation::new(b"node")
+    }
+
+    #[test]
+    fn test_xmss_key_pair() {
+        let node = node();
+        let master_seed = Sha256::digest(b"master seed");
+        let (mut secret, public) = XmssKeyPair::generate::<A, _>(master_seed, 2, &node).unwrap();
+        assert_eq!(secret.index(), 0);
+        assert_eq!(secret.capacity(), 4);
+        assert_ne!(public.public_seed(), &master_seed);
+
+        let (_, again) = XmssKeyPair::generate::<A, _>(master_seed, 2, &node).unwrap();
+        assert_eq!(again.root(), public.root());
+        assert_eq!(again.public_seed(), public.public_seed());
+
+        // the same root under another public seed
+        let mut bytes = public.to_bytes();
+        bytes[0] ^= 1;
+        let other_seed = XmssPublic::<A>::from_bytes(&bytes).unwrap();
+
+        for i in 0..4u8 {
+            let message = Sha256::digest(&[i]);
+            let signature = secret.sign_next(message, &node).unwrap();
+            assert!(public.verify(&signature, message, &node));
+            assert!(!public.verify(&signature, Sha256::digest(b"other"), &node));
+
+            let decoded = XmssSignature::<A, _>::from_bytes(&signature.to_bytes()).unwrap();
+            assert!(public.verify(&decoded, message, &node));
+            assert!(!other_seed.verify(&decoded, message, &node));
+            assert!(decoded.verify(public.public_seed(), public.root(), message, &node));
+        }
+        assert!(secret.sign_next(Sha256::digest(b"message"), &node).is_err());
+    }
+
+    #[test]
+    fn test_xmss_public_bytes() {
+        let node = node();
+        let (_, public) =
+            XmssKeyPair::generate::<A, _>(Sha256::digest(b"master seed"), 1, &node).unwrap();
+        let bytes = public.to_bytes();
+        assert_eq!(bytes.len(), XmssPublic::<A>::size());
+        assert_eq!(bytes[..32], public.public_seed()[..]);
+        assert_eq!(bytes[32..], public.root()[..]);
+
+        let decoded = XmssPublic::<A>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.root(), public.root());
+        assert_eq!(decoded.public_seed(), public.public_seed());
+
+        assert_eq!(
+            XmssPublic::<A>::from_bytes(&bytes[1..]).err(),
+            Some(DecodeError::WrongLength {
+                expected: 64,
+                got: 63,
+            }),
+        );
+        let mut long = bytes;
+        long.push(0);
+        assert!(XmssPublic::<A>::from_bytes(&long).is_err());
+    }
+
+    #[test]
+    fn test_audit_tree() {
+        let node = node();
+        let master_seed = Sha256::digest(b"master seed");
+        for &height in &[0, 1, 3] {
+            let (_, public) = XmssKeyPair::generate::<A, _>(master_seed, height, &node).unwrap();
+            let root = audit_tree::<A, _>(&master_seed, public.public_seed(), height, &node);
+            assert_eq!(root.as_ref(), Ok(public.root()));
+        }
+        let (_, public) = XmssKeyPair::generate::<A, _>(master_seed, 1, &node).unwrap();
+        let other = Sha256::digest(b"other");
+        assert_ne!(
+            audit_tree::<A, _>(&master_seed, &other, 1, &node).as_ref(),
+            Ok(public.root()),
+        );
+        assert_eq!(
+            audit_tree::<A, _>(&master_seed, &other, 64, &node),
+            Err(SignerError::BadHeight { height: 64 }),
+        );
+    }
+
+    #[test]
+    fn test_generate_resumable() {
+        let node = node();
+        let master_seed = Sha256::digest(b"master seed");
+        let (_, expected) = XmssKeyPair::generate::<A, _>(master_seed, 3, &node).unwrap();
+
+        // interrupted after 3 and then after 5 leaves, the progress is persisted
+        let mut stored = Vec::new();
+        let step =
+            XmssKeyPair::generate_resumable::<A, _, _>(master_seed, 3, 3, &node, |progress| {
+                stored = progress.to_bytes();
+                false
+            })
+            .unwrap();
+        let progress = match step {
+            KeyGenStep::Paused(progress) => progress,
+            KeyGenStep::Done(..) => panic!("the checkpoint stops the generation"),
+        };
+        assert_eq!(progress.done(), 3);
+        assert_eq!(stored.len(), 9 + 2 * 32);
+
+        let progress = KeyGenProgress::<A>::from_bytes(&stored).unwrap();
+        let mut checkpoints = 0;
+        let step = XmssKeyPair::resume(master_seed, progress, 1, &node, |_| {
+            checkpoints += 1;
+            checkpoints < 2
+        });
+        let progress = match step {
+            KeyGenStep::Paused(progress) => progress,
+            KeyGenStep::Done(..) => panic!("the checkpoint stops the generation"),
+        };
+        assert_eq!(progress.done(), 5);
+
+        let progress = KeyGenProgress::<A>::from_bytes(&progress.to_bytes()).unwrap();
+        let (secret, public) = match XmssKeyPair::resume(master_seed, progress, 1, &node, |_| true)
+        {
+            KeyGenStep::Done(secret, public) => (secret, public),
+            KeyGenStep::Paused(_) => panic!("the checkpoint does not stop the generation"),
+        };
+        assert_eq!(public.root(), expected.root());
+        assert_eq!(public.public_seed(), expected.public_seed());
+        assert_eq!(secret.root(&node), *expected.root());
+
+        assert!(KeyGenProgress::<A>::from_bytes(&stored[..40]).is_err());
+        let mut bad = stored;
+        bad[0] = 64;
+        assert_eq!(
+            KeyGenProgress::<A>::from_bytes(&bad).err(),
+            Some(SignerError::BadHeight { height: 64 }),
+        );
+        assert!(KeyGenProgress::<A>::new(64).is_err());
+        assert!(
+            XmssKeyPair::generate_resumable::<A, _, _>(master_seed, 64, 1, &node, |_| true)
+                .is_err()
+        );
+        assert!(XmssKeyPair::generate::<A, _>(master_seed, 64, &node).is_err());
+    }
+
+    #[test]
+    fn test_leaf_stream() {
+        let master_seed = Sha256::digest(b"master seed");
+        let signer = Signer::<A>::new(master_seed, 3).unwrap();
+        let public_seed = *signer.public_seed();
+        let stream = || LeafStream::<A, _>::new(master_seed, public_seed, node());
+        let node = node();
+        let leaves = leaves_tree::<A, _>(&master_seed, &public_seed, 3, &node)
+            .unwrap()
+            .0;
+        assert!(stream().finish().is_none());
+        for count in 1..=leaves.len() {
+            let mut stream = stream();
+            (0..count).for_each(|_| stream.push());
+            assert_eq!(stream.count(), count as u64);
+            let root = XmssTree(leaves[..count].to_vec()).collapse(&node);
+            assert_eq!(stream.finish(), Some(root));
+        }
+
+        let mut stream = stream();
+        (0..8).for_each(|_| stream.push());
+        assert_eq!(stream.finish(), Some(signer.root(&node)));
+    }
+
+    #[test]
+    fn test_xmss_public_commitment() {
+        let node = node();
+        let (_, public) =
+            XmssKeyPair::generate::<A, _>(Sha256::digest(b"master seed"), 1, &node).unwrap();
+        let mut input = b"w-ots public key".to_vec();
+        input.extend_from_slice(&public.to_bytes());
+        assert_eq!(public.commitment(), Sha256::digest(&input));
+
+        let decoded = XmssPublic::<A>::from_bytes(&public.to_bytes()).unwrap();
+        assert_eq!(decoded.commitment(), public.commitment());
+        let (_, other) =
+            XmssKeyPair::generate::<A, _>(Sha256::digest(b"other seed"), 1, &node).unwrap();
+        assert_ne!(other.commitment(), public.commitment());
+    }
+
+    #[cfg(feature = "mnemonic")]
+    #[test]
+    fn test_from_mnemonic_seed() {
+        let node = node();
+        let seed = [7; 64];
+        let (secret, public) = XmssKeyPair::from_mnemonic_seed::<A, _>(&seed, 1, &node).unwrap();
+        let (again, public_again) =
+            XmssKeyPair::from_mnemonic_seed::<A, _>(&seed, 1, &node).unwrap();
+        assert_eq!(secret.to_bytes(), again.to_bytes());
+        assert_eq!(public.to_bytes(), public_again.to_bytes());
+
+        let master_seed = Sha256::new()
+            .chain(b"w-ots mnemonic")
+            .chain(&seed[..])
+            .result();
+        let (_, expected) = XmssKeyPair::generate::<A, _>(master_seed, 1, &node).unwrap();
+        assert_eq!(public.to_bytes(), expected.to_bytes());
+
+        let (_, other) = XmssKeyPair::from_mnemonic_seed::<A, _>(&[8; 64], 1, &node).unwrap();
+        assert_ne!(public.root(), other.root());
+    }
+}
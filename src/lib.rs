@@ -1,10 +1,61 @@
 #![forbid(unsafe_code)]
 #![allow(non_shorthand_field_patterns)]
+// the baseline test of `XmssTree::collapse` implements the operation inside the test
+#![cfg_attr(test, allow(non_local_definitions))]
 
+#[cfg(test)]
+mod sha256;
+#[cfg(test)]
+mod reference;
+
+mod ct;
+mod error;
 mod state;
 mod signature;
 mod xmss;
+mod xmss_signature;
+mod seed;
+mod signer;
+mod params;
+mod aggregate;
+mod keypair;
+mod dyn_wots;
+mod prf;
+mod address;
+#[cfg(feature = "zeroize")]
+mod wipe;
+
+#[cfg(feature = "interop")]
+pub mod interop;
+
+pub use self::error::{DecodeError, ParamError, MessageError, XmssError, SignerError};
 
-pub use self::state::WOtsPlus;
-pub use self::signature::{SecretKey, PublicKey, Signature};
-pub use self::xmss::{XmssOperation, XmssPath, XmssTree};
+pub use self::state::{WOtsPlus, State, Message, MessageBuilder, fit_digest, chain_advance};
+pub use self::signature::{
+    SecretKey, PublicKey, Signature, verify_with_message, verify_lazy, key_pair_matches, sign_raw,
+    verify_checksum_chains, align, sign_root, verify_root, verify_detailed,
+    verify_with_shared_randomization,
+};
+pub use self::xmss::{
+    XmssOperation, HashOperation, XmssPath, XmssTree, CachedXmssTree, build_tree, join,
+    forest_root, Sorted, OddNode, LazyXmssTree, same_root, MAX_HEIGHT,
+};
+pub use self::xmss_signature::{XmssSignature, CompactXmssSignature, XmssBatchItem};
+pub use self::xmss_signature::{verify_xmss_rootonly, verify_xmss_batch, verify_against_cached_tree};
+pub use self::xmss_signature::leaf_from_signature;
+pub use self::seed::{
+    wots_secret_for_index, leaves_iter, leaves_tree, leaf_public_key, height_for_capacity,
+};
+pub use self::signer::Signer;
+pub use self::signature::verify_tagged;
+pub use self::params::{
+    SizeInfo, serialized_len, xmss_signature_len, HashId, DigestId, ChecksumPlacement, ParamHeader,
+    ParamReport, compare_params,
+};
+pub use self::aggregate::{AggregateSignature, segments, sign_aggregate, verify_aggregate};
+pub use self::keypair::{XmssSecret, XmssPublic, XmssKeyPair, KeyGenStep, KeyGenProgress};
+pub use self::keypair::LeafStream;
+pub use self::keypair::audit_tree;
+pub use self::dyn_wots::{Winternitz, DynWots, supported_winternitz, is_supported};
+pub use self::prf::{Prf, Hmac};
+pub use self::address::{Address, prf_address, prf_keygen, wots_secret_keygen};
@@ -0,0 +1,331 @@
+use core::fmt;
+use digest::generic_array::typenum::Unsigned;
+
+use super::state::{WOtsPlus, State, lengths_for, validate_winternitz};
+use super::error::{DecodeError, ParamError};
+
+/// Sizes in bytes of the serialized objects of the parameter set
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SizeInfo {
+    pub secret_key: usize,
+    pub public_key: usize,
+    pub signature: usize,
+    pub xmss_path_per_level: usize,
+}
+
+pub const fn serialized_len<A>() -> SizeInfo
+where
+    A: WOtsPlus,
+{
+    SizeInfo {
+        secret_key: State::<A>::size(),
+        public_key: State::<A>::size(),
+        signature: State::<A>::size(),
+        xmss_path_per_level: A::BlockLength::USIZE,
+    }
+}
+
+/// The length of `XmssSignature::to_bytes` of the tree of the height,
+/// the index as `u64`, the one time signature and a sibling per level
+pub const fn xmss_signature_len<A>(height: usize) -> usize
+where
+    A: WOtsPlus,
+{
+    8 + State::<A>::size() + height * A::BlockLength::USIZE
+}
+
+/// The costs of a choice of `w`, the sizes are in blocks, the hash counts
+/// are of the chain steps, the worst cases are the bound `chains * (w - 1)`,
+/// the checksum keeps the real ones lower, the average treats each digit as uniform
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ParamReport {
+    pub w: usize,
+    pub l1: usize,
+    pub l2: usize,
+    pub chains: usize,
+    pub signature_blocks: usize,
+    pub key_generation_hashes: usize,
+    pub sign_worst_hashes: usize,
+    pub sign_average_hashes: usize,
+    pub verify_worst_hashes: usize,
+    pub verify_average_hashes: usize,
+}
+
+/// The report of each `w` for the message of `message_size` bytes, no hashing,
+/// the signing and the verification together always take `chains * (w - 1)` steps
+pub fn compare_params(
+    message_size: usize,
+    candidates: &[usize],
+) -> Result<Vec<ParamReport>, ParamError> {
+    if message_size == 0 {
+        return Err(ParamError::ZeroMessageSize);
+    }
+    candidates
+        .iter()
+        .map(|&w| {
+            validate_winternitz(w.saturating_sub(1))?;
+            let (l1, l2) = lengths_for(message_size, w - 1);
+            let chains = l1 + l2;
+            let steps = chains * (w - 1);
+            Ok(ParamReport {
+                w,
+                l1,
+                l2,
+                chains,
+                signature_blocks: (w - 1) + chains,
+                key_generation_hashes: steps,
+                sign_worst_hashes: steps,
+                sign_average_hashes: steps / 2,
+                verify_worst_hashes: steps,
+                verify_average_hashes: steps - steps / 2,
+            })
+        })
+        .collect()
+}
+
+/// Registry of the hash functions, the identity is written to the tagged serialization,
+/// so the objects made with a different hash are rejected rather than misparsed
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum HashId {
+    Unspecified = 0,
+    Sha256 = 1,
+    Sha512_256 = 2,
+    Sha512 = 3,
+    Sha3_256 = 4,
+    Sha3_512 = 5,
+    Blake2s256 = 6,
+    Blake2b512 = 7,
+    Shake128 = 8,
+    Shake256 = 9,
+}
+
+/// The identity of the digest in the registry, for the runtime parameters
+/// to check the hash they name against the hash they compute
+pub trait DigestId {
+    const HASH_ID: HashId;
+}
+
+#[cfg(test)]
+impl DigestId for super::sha256::Sha256 {
+    const HASH_ID: HashId = HashId::Sha256;
+}
+
+/// Where the checksum digits are, after the message digits as in RFC 8391,
+/// or before them as some other implementations do
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChecksumPlacement {
+    Append,
+    Prepend,
+}
+
+/// The parameter set written before the tagged object, the hash id byte,
+/// then `BlockLength`, `WinternitzMinusOne` and `MessageSize` as big endian `u16`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ParamHeader {
+    pub hash_id: u8,
+    pub block_length: u16,
+    pub winternitz_minus_one: u16,
+    pub message_size: u16,
+}
+
+impl fmt::Display for ParamHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hash id: {}, n: {}, w - 1: {}, m: {}",
+            self.hash_id, self.block_length, self.winternitz_minus_one, self.message_size,
+        )
+    }
+}
+
+impl ParamHeader {
+    pub const SIZE: usize = 7;
+
+    pub fn of<A>() -> Self
+    where
+        A: WOtsPlus,
+    {
+        ParamHeader {
+            hash_id: A::HASH_ID as u8,
+            block_length: A::BlockLength::U16,
+            winternitz_minus_one: A::WinternitzMinusOne::U16,
+            message_size: A::MessageSize::U16,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [self.hash_id, 0, 0, 0, 0, 0, 0];
+        bytes[1..3].copy_from_slice(&self.block_length.to_be_bytes());
+        bytes[3..5].copy_from_slice(&self.winternitz_minus_one.to_be_bytes());
+        bytes[5..7].copy_from_slice(&self.message_size.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() != Self::SIZE {
+            return Err(DecodeError::WrongLength {
+                expected: Self::SIZE,
+                got: bytes.len(),
+            });
+        }
+        Ok(ParamHeader {
+            hash_id: bytes[0],
+            block_length: u16::from_be_bytes([bytes[1], bytes[2]]),
+            winternitz_minus_one: u16::from_be_bytes([bytes[3], bytes[4]]),
+            message_size: u16::from_be_bytes([bytes[5], bytes[6]]),
+        })
+    }
+
+    /// Split the header off the tagged bytes and check it is the one of `A`
+    pub(crate) fn strip<A>(bytes: &[u8]) -> Result<&[u8], DecodeError>
+    where
+        A: WOtsPlus,
+    {
+        let size = Self::SIZE.min(bytes.len());
+        let got = Self::from_bytes(&bytes[..size])?;
+        let expected = Self::of::<A>();
+        if got != expected {
+            return Err(DecodeError::ParamMismatch { expected, got });
+        }
+        Ok(&bytes[size..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use digest::{
+        generic_array::typenum::{U16, U32, U15},
+        Digest,
+    };
+    use super::super::{
+        sha256::Sha256, WOtsPlus, State, HashOperation, XmssKeyPair, SecretKey, PublicKey,
+        Signature, DecodeError, ParamError,
+    };
+    use super::{
+        SizeInfo, serialized_len, xmss_signature_len, ParamReport, compare_params, HashId,
+        ParamHeader,
+    };
+
+    type A = (U32, U32, U15, Sha256, ());
+
+    #[test]
+    fn test_serialized_len() {
+        const SIZE: SizeInfo = serialized_len::<A>();
+        assert_eq!(State::<A>::lengths(), (64, 3));
+        assert_eq!(
+            SIZE,
+            SizeInfo {
+                secret_key: (15 + 67) * 32,
+                public_key: (15 + 67) * 32,
+                signature: (15 + 67) * 32,
+                xmss_path_per_level: 32,
+            },
+        );
+
+        let secret_key = SecretKey::<A>::from_seed(&Default::default());
+        let signature = Signature::sign(&secret_key, Default::default());
+        assert_eq!(signature.to_bytes().len(), SIZE.signature);
+    }
+
+    #[test]
+    fn test_xmss_signature_len() {
+        const LEN: usize = xmss_signature_len::<A>(2);
+        assert_eq!(LEN, 8 + 15 * 32 + 67 * 32 + 2 * 32);
+
+        let node = HashOperation::<Sha256>::new(b"node");
+        let (mut secret, _) =
+            XmssKeyPair::generate::<A, _>(Sha256::digest(b"master seed"), 2, &node).unwrap();
+        let signature = secret.sign_next(Sha256::digest(b"message"), &node).unwrap();
+        assert_eq!(signature.to_bytes().len(), LEN);
+    }
+
+    #[test]
+    fn test_tagged() {
+        type B = (U32, U16, U15, Sha256, ());
+
+        struct Tagged;
+
+        impl WOtsPlus for Tagged {
+            type BlockLength = U32;
+            type MessageSize = U32;
+            type WinternitzMinusOne = U15;
+            type Digest = Sha256;
+
+            const HASH_ID: HashId = HashId::Sha256;
+        }
+
+        let header = ParamHeader::of::<Tagged>();
+        assert_eq!(header.to_bytes(), [1, 0, 32, 0, 15, 0, 32]);
+        assert_eq!(ParamHeader::from_bytes(&header.to_bytes()), Ok(header));
+
+        let secret_key = SecretKey::<A>::from_seed(&Sha256::digest(b"seed"));
+        let public_key = PublicKey::from_secret(&secret_key);
+        let bytes = public_key.to_bytes_tagged();
+        assert_eq!(bytes[..7], ParamHeader::of::<A>().to_bytes());
+        assert_eq!(bytes[7..], public_key.to_bytes()[..]);
+        let decoded = PublicKey::<A>::from_bytes_tagged(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), public_key.to_bytes());
+
+        let signature = Signature::sign(&secret_key, Sha256::digest(b"message"));
+        let bytes = signature.to_bytes_tagged();
+        assert!(Signature::<A>::from_bytes_tagged(&bytes).is_ok());
+        assert_eq!(
+            Signature::<Tagged>::from_bytes_tagged(&bytes).err(),
+            Some(DecodeError::ParamMismatch {
+                expected: header,
+                got: ParamHeader::of::<A>(),
+            }),
+        );
+        assert!(Signature::<B>::from_bytes_tagged(&bytes).is_err());
+        assert!(Signature::<A>::from_bytes_tagged(&bytes[..3]).is_err());
+
+        let bytes = secret_key.to_bytes_tagged();
+        let decoded = SecretKey::<A>::from_bytes_tagged(&bytes).unwrap();
+        assert_eq!(decoded.data(), secret_key.data());
+    }
+
+    #[test]
+    fn test_compare_params() {
+        let reports = compare_params(32, &[16, 256]).unwrap();
+        assert_eq!(
+            reports[0],
+            ParamReport {
+                w: 16,
+                l1: 64,
+                l2: 3,
+                chains: 67,
+                signature_blocks: 15 + 67,
+                key_generation_hashes: 1005,
+                sign_worst_hashes: 1005,
+                sign_average_hashes: 502,
+                verify_worst_hashes: 1005,
+                verify_average_hashes: 503,
+            },
+        );
+        assert_eq!(
+            reports[1],
+            ParamReport {
+                w: 256,
+                l1: 32,
+                l2: 2,
+                chains: 34,
+                signature_blocks: 255 + 34,
+                key_generation_hashes: 8670,
+                sign_worst_hashes: 8670,
+                sign_average_hashes: 4335,
+                verify_worst_hashes: 8670,
+                verify_average_hashes: 4335,
+            },
+        );
+
+        assert_eq!(
+            compare_params(32, &[16, 10]),
+            Err(ParamError::WinternitzNotPowerOfTwoMinusOne {
+                winternitz_minus_one: 9,
+            }),
+        );
+        assert!(compare_params(32, &[0]).is_err());
+        assert!(compare_params(32, &[2]).is_err());
+        assert!(compare_params(0, &[16]).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use digest::{
+    generic_array::{GenericArray, typenum::Unsigned},
+    BlockInput, Digest,
+};
+
+/// The keyed function the secret chains and the randomization are derived with,
+/// it is the sixth element of the parameters tuple, the five elements tuple
+/// uses the plain hash `()`, which is not HMAC, pass `Hmac` for SHA-2
+pub trait Prf<D>
+where
+    D: Digest,
+{
+    fn prf(key: &[u8], input: &[u8]) -> GenericArray<u8, D::OutputSize>;
+}
+
+/// `H(key || input)`, fine for the hash without length extension, e.g. SHA-3
+impl<D> Prf<D> for ()
+where
+    D: Digest,
+{
+    fn prf(key: &[u8], input: &[u8]) -> GenericArray<u8, D::OutputSize> {
+        D::new().chain(key).chain(input).result()
+    }
+}
+
+/// HMAC of RFC 2104, prefer it for SHA-2, which is subject to length extension
+pub struct Hmac;
+
+impl<D> Prf<D> for Hmac
+where
+    D: Digest + BlockInput,
+{
+    fn prf(key: &[u8], input: &[u8]) -> GenericArray<u8, D::OutputSize> {
+        let block_size = D::BlockSize::USIZE;
+        let mut padded = vec![0; block_size];
+        if key.len() > block_size {
+            let hashed = D::digest(key);
+            padded[..hashed.len()].copy_from_slice(&hashed);
+        } else {
+            padded[..key.len()].copy_from_slice(key);
+        }
+
+        let pad = |byte: u8| padded.iter().map(|k| k ^ byte).collect::<Vec<_>>();
+        let inner = D::new().chain(pad(0x36)).chain(input).result();
+        D::new().chain(pad(0x5c)).chain(inner).result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use digest::{
+        generic_array::typenum::{U32, U15},
+        Digest,
+    };
+    use super::super::{sha256::Sha256, SecretKey};
+    use super::{Prf, Hmac};
+
+    #[test]
+    fn test_hmac() {
+        // RFC 4231, the test cases 1 and 6
+        let mac = <Hmac as Prf<Sha256>>::prf(&[0x0b; 20], b"Hi There");
+        assert_eq!(
+            hex::encode(mac),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+        );
+        let mac = <Hmac as Prf<Sha256>>::prf(
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+        );
+        assert_eq!(
+            hex::encode(mac),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+        );
+    }
+
+    #[test]
+    fn test_from_seed_with_hmac() {
+        type Plain = (U32, U32, U15, Sha256, ());
+        type Unkeyed = (U32, U32, U15, Sha256, (), ());
+        type Keyed = (U32, U32, U15, Sha256, (), Hmac);
+
+        let seed = Sha256::digest(b"seed");
+        let a = SecretKey::<Keyed>::from_seed(&seed);
+        assert_eq!(
+            a.to_bytes(),
+            SecretKey::<Keyed>::from_seed(&seed).to_bytes()
+        );
+        assert_ne!(
+            a.to_bytes(),
+            SecretKey::<Plain>::from_seed(&seed).to_bytes()
+        );
+
+        assert_eq!(
+            SecretKey::<Unkeyed>::from_seed(&seed).to_bytes(),
+            SecretKey::<Plain>::from_seed(&seed).to_bytes()
+        );
+
+        let expected = <Hmac as Prf<Sha256>>::prf(&seed, &[0, 0, 0, 0]);
+        assert_eq!(a.randomization()[0], expected);
+
+        let a = a.rerandomize_from_seed(b"epoch");
+        let b = SecretKey::<Keyed>::from_seed(&seed).rerandomize_from_seed(b"epoch");
+        assert_eq!(a.to_bytes(), b.to_bytes());
+        assert_eq!(
+            a.randomization()[0],
+            <Hmac as Prf<Sha256>>::prf(b"epoch", &[0; 4])
+        );
+    }
+}
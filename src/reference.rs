@@ -0,0 +1,298 @@
+// Deliberately simple second implementation of the scheme, the production code
+// is checked against it, it allocates freely and does not share any helper
+
+use digest::{
+    generic_array::{
+        GenericArray,
+        typenum::{Unsigned, U16, U32, U3, U7, U15, U255},
+    },
+    Digest,
+};
+
+use super::{
+    sha256::Sha256,
+    state::{WOtsPlus, State},
+    SecretKey, PublicKey, Signature, XmssOperation, HashOperation, XmssTree,
+};
+
+fn to_vec<L>(block: &GenericArray<u8, L>) -> Vec<u8>
+where
+    L: digest::generic_array::ArrayLength<u8>,
+{
+    block.iter().cloned().collect()
+}
+
+fn digits<A>(message: &[u8]) -> Vec<usize>
+where
+    A: WOtsPlus,
+{
+    let w = A::WinternitzMinusOne::USIZE + 1;
+    let bits = w.trailing_zeros() as usize;
+    let (l1, l2) = State::<A>::lengths();
+
+    let mut message_bits = Vec::new();
+    for byte in message {
+        for i in (0..8).rev() {
+            message_bits.push(((*byte as usize) >> i) & 1);
+        }
+    }
+    let message_digits = message_bits
+        .chunks(bits)
+        .map(|chunk| chunk.iter().fold(0, |digit, bit| (digit << 1) | bit))
+        .collect::<Vec<_>>();
+    let message_digits = message_digits[(message_digits.len() - l1)..].to_vec();
+
+    let mut sum = message_digits.iter().map(|d| w - 1 - d).sum::<usize>();
+    let mut checksum_digits = vec![0; l2];
+    for i in (0..l2).rev() {
+        checksum_digits[i] = sum % w;
+        sum /= w;
+    }
+
+    let mut all = message_digits;
+    all.extend(checksum_digits);
+    all
+}
+
+fn chain<A>(block: Vec<u8>, masks: &[Vec<u8>], start: usize, steps: usize) -> Vec<u8>
+where
+    A: WOtsPlus,
+{
+    let mut block = block;
+    for mask in &masks[start..(start + steps)] {
+        let x = block
+            .iter()
+            .zip(mask)
+            .map(|(a, b)| a ^ b)
+            .collect::<Vec<u8>>();
+        block = to_vec(&A::Digest::digest(&x));
+    }
+    block
+}
+
+fn addressed_chain<A>(
+    block: Vec<u8>,
+    masks: &[Vec<u8>],
+    address: (&[u8], u64, usize),
+    start: usize,
+    steps: usize,
+) -> Vec<u8>
+where
+    A: WOtsPlus,
+{
+    let (public_seed, leaf, index) = address;
+    let mut block = block;
+    for (step, mask) in masks.iter().enumerate().skip(start).take(steps) {
+        let mut input = public_seed.to_vec();
+        input.push(1);
+        input.extend(leaf.to_be_bytes());
+        input.extend((index as u32).to_be_bytes());
+        input.extend((step as u32).to_be_bytes());
+        let address_mask = to_vec(&A::Digest::digest(&input));
+        let x = block
+            .iter()
+            .zip(mask)
+            .zip(address_mask)
+            .map(|((a, b), c)| a ^ b ^ c)
+            .collect::<Vec<u8>>();
+        block = to_vec(&A::Digest::digest(&x));
+    }
+    block
+}
+
+struct Key {
+    masks: Vec<Vec<u8>>,
+    chains: Vec<Vec<u8>>,
+}
+
+fn key<A>(secret_key: &SecretKey<A>) -> Key
+where
+    A: WOtsPlus,
+{
+    Key {
+        masks: secret_key.randomization().iter().map(to_vec).collect(),
+        chains: secret_key.data().iter().map(to_vec).collect(),
+    }
+}
+
+fn tips<A>(key: &Key) -> Vec<Vec<u8>>
+where
+    A: WOtsPlus,
+{
+    let w = A::WinternitzMinusOne::USIZE;
+    key.chains
+        .iter()
+        .map(|c| chain::<A>(c.clone(), &key.masks, 0, w))
+        .collect()
+}
+
+fn sign<A>(key: &Key, message: &[u8]) -> Vec<u8>
+where
+    A: WOtsPlus,
+{
+    let mut output = Vec::new();
+    for mask in &key.masks {
+        output.extend(mask.clone());
+    }
+    for (c, d) in key.chains.iter().zip(digits::<A>(message)) {
+        output.extend(chain::<A>(c.clone(), &key.masks, 0, d));
+    }
+    output
+}
+
+fn verify<A>(public_key: &[Vec<u8>], message: &[u8], signature: &[u8]) -> bool
+where
+    A: WOtsPlus,
+{
+    let n = A::BlockLength::USIZE;
+    let w = A::WinternitzMinusOne::USIZE;
+    let blocks = signature.chunks(n).map(|c| c.to_vec()).collect::<Vec<_>>();
+    let (masks, chains) = blocks.split_at(w);
+    digits::<A>(message)
+        .into_iter()
+        .enumerate()
+        .all(|(i, d)| chain::<A>(chains[i].clone(), masks, d, w - d) == public_key[i])
+}
+
+fn node<T, F>(leaves: &[T], level: usize, index: usize, f: &F) -> T
+where
+    T: Clone,
+    F: XmssOperation<T>,
+{
+    if level == 0 {
+        return leaves[index].clone();
+    }
+    let below = leaves.len().div_ceil(1 << (level - 1));
+    let left = node(leaves, level - 1, 2 * index, f);
+    if 2 * index + 1 < below {
+        let right = node(leaves, level - 1, 2 * index + 1, f);
+        f.operation(level - 1, left, right)
+    } else {
+        left
+    }
+}
+
+fn collapse<T, F>(leaves: &[T], f: &F) -> T
+where
+    T: Clone,
+    F: XmssOperation<T>,
+{
+    let mut height = 0;
+    while (1 << height) < leaves.len() {
+        height += 1;
+    }
+    node(leaves, height, 0, f)
+}
+
+struct Random(Vec<u8>);
+
+impl Random {
+    fn block<L>(&mut self) -> GenericArray<u8, L>
+    where
+        L: digest::generic_array::ArrayLength<u8>,
+    {
+        let mut output = Vec::new();
+        while output.len() < L::USIZE {
+            self.0 = to_vec(&Sha256::digest(&self.0));
+            output.extend(self.0.clone());
+        }
+        GenericArray::clone_from_slice(&output[..L::USIZE])
+    }
+}
+
+fn operation() -> HashOperation<Sha256> {
+    HashOperation::new(b"node")
+}
+
+fn differential<A>(random: &mut Random)
+where
+    A: WOtsPlus<BlockLength = U32>,
+{
+    for _ in 0..4 {
+        let secret_key = SecretKey::<A>::from_seed(&random.block());
+        let reference = key(&secret_key);
+        let message = random.block::<A::MessageSize>();
+        let other = random.block::<A::MessageSize>();
+
+        let public_key = PublicKey::from_secret(&secret_key);
+        let reference_public_key = tips::<A>(&reference);
+        let reference_leaves = reference_public_key
+            .iter()
+            .map(|tip| GenericArray::clone_from_slice(tip))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            public_key.leaf(&operation()),
+            collapse(&reference_leaves, &operation())
+        );
+
+        let signature = Signature::sign(&secret_key, message.clone());
+        let bytes = signature.to_bytes();
+        assert_eq!(bytes, sign::<A>(&reference, &message));
+        assert!(signature.verify(&public_key, message.clone()));
+        assert!(verify::<A>(&reference_public_key, &message, &bytes));
+        assert_eq!(
+            signature.verify(&public_key, other.clone()),
+            verify::<A>(&reference_public_key, &other, &bytes),
+        );
+    }
+}
+
+#[test]
+fn test_differential_wots() {
+    let mut random = Random(b"differential".to_vec());
+    differential::<(U32, U32, U15, Sha256, ())>(&mut random);
+    differential::<(U32, U32, U255, Sha256, ())>(&mut random);
+    differential::<(U32, U16, U15, Sha256, ())>(&mut random);
+    differential::<(U32, U16, U255, Sha256, ())>(&mut random);
+    differential::<(U32, U32, U7, Sha256, ())>(&mut random);
+    differential::<(U32, U16, U3, Sha256, ())>(&mut random);
+}
+
+#[test]
+fn test_differential_collapse() {
+    let mut random = Random(b"collapse".to_vec());
+    for n in 1..40 {
+        let leaves = (0..n).map(|_| random.block::<U32>()).collect::<Vec<_>>();
+        assert_eq!(
+            XmssTree(leaves.clone()).collapse(&operation()),
+            collapse(&leaves, &operation()),
+        );
+    }
+}
+
+#[test]
+fn test_differential_address() {
+    type A = (U32, U32, U15, Sha256, ());
+
+    let mut random = Random(b"address".to_vec());
+    let public_seed = random.block::<U32>();
+    for leaf in [0, 1, 1 << 40] {
+        let secret_key = SecretKey::<A>::from_seed(&random.block());
+        let reference = key(&secret_key);
+        let secret_key = secret_key.with_address(public_seed, leaf);
+        let message = random.block::<U32>();
+
+        let public_key = PublicKey::from_secret(&secret_key);
+        let signature = Signature::sign(&secret_key, message);
+        let reference_digits = digits::<A>(&message);
+        for (i, c) in reference.chains.iter().enumerate() {
+            let address = (public_seed.as_ref(), leaf, i);
+            let tip = addressed_chain::<A>(c.clone(), &reference.masks, address, 0, 15);
+            assert_eq!(tip, to_vec(&public_key.tips()[i]));
+            let d = reference_digits[i];
+            let signed = addressed_chain::<A>(c.clone(), &reference.masks, address, 0, d);
+            assert_eq!(signed, to_vec(&signature.data()[i]));
+        }
+        assert!(signature.verify(&public_key, message));
+
+        let decoded = Signature::<A>::from_bytes(&signature.to_bytes()).unwrap();
+        assert!(!decoded.verify(&public_key, message));
+        assert!(!decoded
+            .clone()
+            .with_address(public_seed, leaf + 1)
+            .verify(&public_key, message));
+        assert!(decoded
+            .with_address(public_seed, leaf)
+            .verify(&public_key, message));
+    }
+}
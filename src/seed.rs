@@ -0,0 +1,338 @@
+use digest::{
+    generic_array::{GenericArray, typenum::Unsigned},
+    Digest,
+};
+use byteorder::{ByteOrder, BigEndian};
+
+use super::state::WOtsPlus;
+use super::signature::{SecretKey, PublicKey};
+use super::xmss::{XmssOperation, XmssTree, build_tree, MAX_HEIGHT};
+use super::error::SignerError;
+use super::address::{Address, wots_secret_keygen};
+
+/// Domain of the derived block, so different derivations never collide
+#[derive(Clone, Copy)]
+pub(crate) enum Domain {
+    Mask = 1,
+    PublicSeed = 2,
+}
+
+/// Counter mode expansion `H(seed || 0) || H(seed || 1) || ...` truncated to `out_len`,
+/// the counter is big endian `u32`
+pub(crate) fn expand<D>(seed: &[u8], out_len: usize) -> Vec<u8>
+where
+    D: Digest,
+{
+    let mut output = Vec::with_capacity(out_len + D::output_size());
+    let mut counter = 0;
+    while output.len() < out_len {
+        output.extend_from_slice(expand_block::<D>(seed, counter).as_ref());
+        counter += 1;
+    }
+    output.truncate(out_len);
+    output
+}
+
+/// The single block `H(seed || counter)` of `expand`
+pub(crate) fn expand_block<D>(seed: &[u8], counter: u32) -> GenericArray<u8, D::OutputSize>
+where
+    D: Digest,
+{
+    let mut buffer = [0; 4];
+    BigEndian::write_u32(&mut buffer, counter);
+    D::new().chain(seed).chain(buffer).result()
+}
+
+/// Same as `expand`, but each block is `prf(seed, counter)`, see `WOtsPlus::prf`,
+/// it is `expand` for the plain hash
+pub(crate) fn expand_keyed<A>(seed: &[u8], out_len: usize) -> Vec<u8>
+where
+    A: WOtsPlus,
+{
+    let mut output = Vec::with_capacity(out_len + A::BlockLength::USIZE);
+    let mut counter = 0;
+    while output.len() < out_len {
+        output.extend_from_slice(expand_keyed_block::<A>(seed, counter).as_ref());
+        counter += 1;
+    }
+    output.truncate(out_len);
+    output
+}
+
+/// The single block `prf(seed, counter)` of `expand_keyed`
+pub(crate) fn expand_keyed_block<A>(seed: &[u8], counter: u32) -> GenericArray<u8, A::BlockLength>
+where
+    A: WOtsPlus,
+{
+    let mut buffer = [0; 4];
+    BigEndian::write_u32(&mut buffer, counter);
+    A::prf(seed, &buffer)
+}
+
+/// `prf(seed, domain || index)`, the index is big endian `u64`
+pub(crate) fn derive<A>(
+    seed: &GenericArray<u8, A::BlockLength>,
+    domain: Domain,
+    index: u64,
+) -> GenericArray<u8, A::BlockLength>
+where
+    A: WOtsPlus,
+{
+    let mut buffer = [domain as u8; 9];
+    BigEndian::write_u64(&mut buffer[1..], index);
+    A::prf(seed, &buffer)
+}
+
+/// `H(public_seed || domain || leaf || chain || step)`, the leaf is big endian `u64`,
+/// the chain and the step are big endian `u32`
+pub(crate) fn mask<A>(
+    public_seed: &GenericArray<u8, A::BlockLength>,
+    leaf: u64,
+    chain: usize,
+    step: usize,
+) -> GenericArray<u8, A::BlockLength>
+where
+    A: WOtsPlus,
+{
+    let mut buffer = [0; 16];
+    BigEndian::write_u64(&mut buffer[..8], leaf);
+    BigEndian::write_u32(&mut buffer[8..12], chain as u32);
+    BigEndian::write_u32(&mut buffer[12..], step as u32);
+    A::Digest::new()
+        .chain(public_seed)
+        .chain([Domain::Mask as u8])
+        .chain(buffer)
+        .result()
+}
+
+/// The secret key of the leaf `index` of an xmss tree, `PRF_keygen` of NIST SP 800-208
+/// with the master seed as the secret seed, see `wots_secret_keygen`, the address is
+/// of the one time key `index` in the tree 0, the indices above `u32::MAX` continue
+/// in the tree address, the same seeds and index always give the same key,
+/// so the signer does not need to store all of them
+pub fn wots_secret_for_index<A>(
+    master_seed: &GenericArray<u8, A::BlockLength>,
+    public_seed: &GenericArray<u8, A::BlockLength>,
+    index: u64,
+) -> SecretKey<A>
+where
+    A: WOtsPlus,
+{
+    let address = Address::ots(0, index >> 32, index as u32);
+    wots_secret_keygen::<A>(master_seed, public_seed, &address)
+}
+
+// the key of the leaf `index` the signer signs with, bound to the leaf
+// by the public seed, see `SecretKey::with_address`
+pub(crate) fn leaf_secret_key<A>(
+    master_seed: &GenericArray<u8, A::BlockLength>,
+    public_seed: &GenericArray<u8, A::BlockLength>,
+    index: u64,
+) -> SecretKey<A>
+where
+    A: WOtsPlus,
+{
+    wots_secret_for_index::<A>(master_seed, public_seed, index)
+        .with_address(public_seed.clone(), index)
+}
+
+/// The leaf `index` of the xmss tree derived from the master seed, the public key of
+/// `wots_secret_for_index` bound to the leaf by the public seed, see `SecretKey::with_address`,
+/// compressed by `PublicKey::leaf`, the signer, the key generation and `audit_tree`
+/// all derive the leaves by it, so they never diverge
+pub fn leaf_public_key<A, F>(
+    master_seed: &GenericArray<u8, A::BlockLength>,
+    public_seed: &GenericArray<u8, A::BlockLength>,
+    index: u64,
+    f: &F,
+) -> GenericArray<u8, A::BlockLength>
+where
+    A: WOtsPlus,
+    F: XmssOperation<GenericArray<u8, A::BlockLength>>,
+{
+    PublicKey::from_secret(&leaf_secret_key::<A>(master_seed, public_seed, index)).leaf(f)
+}
+
+// the heights above `MAX_HEIGHT` are rejected before anything is derived
+pub(crate) fn check_height(height: usize) -> Result<(), SignerError> {
+    match height > MAX_HEIGHT {
+        true => Err(SignerError::BadHeight { height }),
+        false => Ok(()),
+    }
+}
+
+// the number of one time keys of the tree of the checked height
+pub(crate) fn capacity(height: usize) -> u64 {
+    assert!(height <= MAX_HEIGHT);
+    1 << height
+}
+
+/// The smallest height of the tree of at least `count` one time keys,
+/// `ceil(log2(count))`, the inverse of `Signer::capacity`, above `2 ^ MAX_HEIGHT`
+/// it is 64, that no tree supports
+pub fn height_for_capacity(count: u64) -> usize {
+    match count {
+        0 | 1 => 0,
+        _ => 64 - (count - 1).leading_zeros() as usize,
+    }
+}
+
+/// The public keys of all leaves of the xmss tree derived from the master seed,
+/// bound to the leaves by the public seed as `leaf_public_key` does, in the order
+/// of the leaves, an auditor collapses their `leaf` into the root,
+/// fails on the height above `MAX_HEIGHT`
+pub fn leaves_iter<'a, A>(
+    master_seed: &'a GenericArray<u8, A::BlockLength>,
+    public_seed: &'a GenericArray<u8, A::BlockLength>,
+    height: usize,
+) -> Result<impl Iterator<Item = PublicKey<A>> + 'a, SignerError>
+where
+    A: WOtsPlus,
+{
+    check_height(height)?;
+    Ok((0..capacity(height)).map(move |index| {
+        PublicKey::from_secret(&leaf_secret_key(master_seed, public_seed, index))
+    }))
+}
+
+/// The leaves of `leaves_iter` compressed by `PublicKey::leaf`, the parallel path of it,
+/// with the `parallel` feature the leaves are derived in several threads, see `build_tree`
+pub fn leaves_tree<A, F>(
+    master_seed: &GenericArray<u8, A::BlockLength>,
+    public_seed: &GenericArray<u8, A::BlockLength>,
+    height: usize,
+    f: &F,
+) -> Result<XmssTree<GenericArray<u8, A::BlockLength>>, SignerError>
+where
+    A: WOtsPlus,
+    F: XmssOperation<GenericArray<u8, A::BlockLength>> + Sync,
+{
+    check_height(height)?;
+    Ok(build_tree(
+        0..capacity(height),
+        |index, f| leaf_public_key::<A, F>(master_seed, public_seed, index, f),
+        f,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use digest::{
+        generic_array::typenum::{U32, U15},
+        Digest,
+    };
+    use super::super::{sha256::Sha256, HashOperation, Signer, XmssTree, SignerError};
+    use super::{
+        expand, wots_secret_for_index, leaves_iter, leaves_tree, leaf_public_key,
+        height_for_capacity, capacity, check_height, MAX_HEIGHT,
+    };
+
+    type A = (U32, U32, U15, Sha256, ());
+
+    #[test]
+    fn test_expand() {
+        let output = expand::<Sha256>(b"seed", 64);
+        assert_eq!(output, expand::<Sha256>(b"seed", 64));
+        assert_eq!(output[..32], expand::<Sha256>(b"seed", 32)[..]);
+        assert_eq!(output[..40], expand::<Sha256>(b"seed", 40)[..]);
+        assert_ne!(output[..32], output[32..]);
+        assert_eq!(
+            output[..32],
+            Sha256::new().chain(b"seed").chain([0, 0, 0, 0]).result()[..]
+        );
+    }
+
+    #[test]
+    fn test_wots_secret_for_index() {
+        let master_seed = Sha256::digest(b"master seed");
+        let public_seed = Sha256::digest(b"public seed");
+        let a = wots_secret_for_index::<A>(&master_seed, &public_seed, 0);
+        let b = wots_secret_for_index::<A>(&master_seed, &public_seed, 1);
+        let c = wots_secret_for_index::<A>(&master_seed, &public_seed, 1);
+        assert_ne!(a.data(), b.data());
+        assert_ne!(a.randomization(), b.randomization());
+        assert_eq!(b.data(), c.data());
+        assert_eq!(b.randomization(), c.randomization());
+        let other_seed = Sha256::digest(b"other public seed");
+        let d = wots_secret_for_index::<A>(&master_seed, &other_seed, 1);
+        assert_ne!(b.data(), d.data());
+
+        // `PRF_keygen` of the one time key 7 of the tree 1, the expected value is
+        // computed from the SP 800-208 definition with Python `hashlib`
+        let secret_seed = Sha256::digest(b"secret seed");
+        let e = wots_secret_for_index::<A>(&secret_seed, &public_seed, (1 << 32) + 7);
+        assert_eq!(
+            hex::encode(e.data()[0]),
+            "14d82223040bc31c57f49403901b902119a08d1df76da90a4e4f0ac8018f76e9",
+        );
+    }
+
+    #[test]
+    fn test_leaves_iter() {
+        let master_seed = Sha256::digest(b"master seed");
+        let node = HashOperation::<Sha256>::new(b"node");
+        let signer = Signer::<A>::new(master_seed, 2).unwrap();
+        let public_seed = signer.public_seed();
+        let public_keys = leaves_iter::<A>(&master_seed, public_seed, 2)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(public_keys.len(), 4);
+        let secret_key = wots_secret_for_index::<A>(&master_seed, public_seed, 3);
+        assert_ne!(
+            public_keys[3].tips(),
+            super::PublicKey::from_secret(&secret_key).tips(),
+        );
+        assert_eq!(
+            public_keys[3].tips(),
+            super::PublicKey::from_secret(&secret_key.with_address(*public_seed, 3)).tips(),
+        );
+
+        let leaves = public_keys
+            .iter()
+            .map(|pk| pk.leaf(&node))
+            .collect::<Vec<_>>();
+        for (index, leaf) in leaves.iter().enumerate() {
+            assert_eq!(
+                *leaf,
+                leaf_public_key::<A, _>(&master_seed, public_seed, index as u64, &node)
+            );
+        }
+        assert_eq!(XmssTree(leaves.clone()).collapse(&node), signer.root(&node));
+        assert_eq!(
+            leaves_tree::<A, _>(&master_seed, public_seed, 2, &node)
+                .unwrap()
+                .0,
+            leaves,
+        );
+        assert!(leaves_tree::<A, _>(&master_seed, public_seed, 64, &node).is_err());
+        let other_seed = Sha256::digest(b"other public seed");
+        assert_ne!(
+            leaf_public_key::<A, _>(&master_seed, &other_seed, 0, &node),
+            leaves[0],
+        );
+        assert!(leaves_iter::<A>(&master_seed, public_seed, 64).is_err());
+    }
+
+    #[test]
+    fn test_height_for_capacity() {
+        assert_eq!(height_for_capacity(0), 0);
+        assert_eq!(height_for_capacity(1), 0);
+        assert_eq!(height_for_capacity(2), 1);
+        assert_eq!(height_for_capacity(3), 2);
+        assert_eq!(height_for_capacity(100_000), 17);
+        for height in 1..MAX_HEIGHT {
+            let power = 1u64 << height;
+            assert_eq!(height_for_capacity(power / 2 + 1), height);
+            assert_eq!(height_for_capacity(power), height);
+            assert_eq!(height_for_capacity(power + 1), height + 1);
+            assert!(capacity(height_for_capacity(power + 1)) > power);
+        }
+        assert_eq!(capacity(MAX_HEIGHT), 1 << 63);
+        // too many for any tree
+        assert_eq!(height_for_capacity(u64::MAX), 64);
+        assert_eq!(
+            check_height(height_for_capacity(u64::MAX)),
+            Err(SignerError::BadHeight { height: 64 }),
+        );
+    }
+}
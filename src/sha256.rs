@@ -0,0 +1,140 @@
+// Minimal SHA-256 used as the digest in tests, the crate does not depend on any
+// concrete hash function
+
+use digest::{
+    generic_array::{
+        GenericArray,
+        typenum::{U32, U64},
+    },
+    Input, BlockInput, FixedOutput, Reset,
+};
+use byteorder::{ByteOrder, BigEndian};
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const H: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+#[derive(Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: Vec<u8>,
+    length: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Sha256 {
+            state: H,
+            buffer: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+}
+
+impl Sha256 {
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 64];
+        BigEndian::read_u32_into(block, &mut w[..16]);
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let mut s = self.state;
+        for i in 0..64 {
+            let s1 = s[4].rotate_right(6) ^ s[4].rotate_right(11) ^ s[4].rotate_right(25);
+            let ch = (s[4] & s[5]) ^ (!s[4] & s[6]);
+            let t1 = s[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = s[0].rotate_right(2) ^ s[0].rotate_right(13) ^ s[0].rotate_right(22);
+            let maj = (s[0] & s[1]) ^ (s[0] & s[2]) ^ (s[1] & s[2]);
+            let t2 = s0.wrapping_add(maj);
+            s = [
+                t1.wrapping_add(t2),
+                s[0],
+                s[1],
+                s[2],
+                s[3].wrapping_add(t1),
+                s[4],
+                s[5],
+                s[6],
+            ];
+        }
+
+        self.state
+            .iter_mut()
+            .zip(s.iter())
+            .for_each(|(a, b)| *a = a.wrapping_add(*b));
+    }
+}
+
+impl Input for Sha256 {
+    fn input<B: AsRef<[u8]>>(&mut self, data: B) {
+        let data = data.as_ref();
+        self.length += data.len() as u64;
+        self.buffer.extend_from_slice(data);
+        let full = self.buffer.len() / 64 * 64;
+        let buffer = self.buffer.drain(..full).collect::<Vec<_>>();
+        buffer.chunks(64).for_each(|block| self.compress(block));
+    }
+}
+
+impl BlockInput for Sha256 {
+    type BlockSize = U64;
+}
+
+impl FixedOutput for Sha256 {
+    type OutputSize = U32;
+
+    fn fixed_result(mut self) -> GenericArray<u8, U32> {
+        let bits = self.length * 8;
+        let mut padding = vec![0x80];
+        padding.resize((119 - self.buffer.len()) % 64 + 1, 0);
+        let mut length = [0; 8];
+        BigEndian::write_u64(&mut length, bits);
+        padding.extend_from_slice(&length);
+        Input::input(&mut self, padding);
+
+        let mut output = GenericArray::default();
+        BigEndian::write_u32_into(&self.state, &mut output);
+        output
+    }
+}
+
+impl Reset for Sha256 {
+    fn reset(&mut self) {
+        *self = Sha256::default();
+    }
+}
+
+#[test]
+fn test_sha256() {
+    use digest::Digest;
+
+    assert_eq!(
+        hex::encode(Sha256::digest(b"abc")),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+    );
+    assert_eq!(
+        hex::encode(Sha256::digest(&[b'a'; 1000])),
+        "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3",
+    );
+}
@@ -1,12 +1,30 @@
-use super::state::{WOtsPlus, State, Message};
+use super::state::{WOtsPlus, State, Message, fit_digest, chain_advance};
+use super::xmss::{XmssOperation, XmssTree};
+use super::error::DecodeError;
+use super::seed;
+use super::ct::ct_eq;
+use super::params::ParamHeader;
 
-use digest::generic_array::GenericArray;
+use digest::generic_array::{GenericArray, typenum::Unsigned};
 
+type Block<A> = GenericArray<u8, <A as WOtsPlus>::BlockLength>;
+
+/// With the `zeroize` feature the secret chains are wiped on drop, the clones too
 #[derive(Clone)]
 pub struct SecretKey<A>(State<A>)
 where
     A: WOtsPlus;
 
+#[cfg(feature = "zeroize")]
+impl<A> Drop for SecretKey<A>
+where
+    A: WOtsPlus,
+{
+    fn drop(&mut self) {
+        self.0.wipe();
+    }
+}
+
 impl<A> SecretKey<A>
 where
     A: WOtsPlus,
@@ -18,46 +36,242 @@ where
         SecretKey(State::new(randomization, data))
     }
 
+    // the state of the key, the moved out key is empty, see `State::take`
+    fn take(mut self) -> State<A> {
+        self.0.take()
+    }
+
+    /// Expand the seed into the randomization followed by the secret chains,
+    /// see `expand` for the exact encoding
+    pub fn from_seed(seed: &GenericArray<u8, A::BlockLength>) -> Self {
+        let bytes = seed::expand_keyed::<A>(seed.as_ref(), State::<A>::size());
+        let secret_key = SecretKey(State::from_bytes(bytes.as_ref()).unwrap());
+        #[cfg(feature = "zeroize")]
+        {
+            let mut bytes = bytes;
+            super::wipe::wipe(&mut bytes);
+        }
+        secret_key
+    }
+
+    /// Bind the chains to the leaf, see `State::with_address`,
+    /// the public key and the signatures inherit the address
+    pub fn with_address(self, public_seed: GenericArray<u8, A::BlockLength>, leaf: u64) -> Self {
+        SecretKey(self.take().with_address(public_seed, leaf))
+    }
+
+    /// The key with the same chains and the randomization of the epoch,
+    /// it is a different key pair, see `State::rerandomize_from_seed`
+    pub fn rerandomize_from_seed(self, epoch_seed: &[u8]) -> Self {
+        SecretKey(self.take().rerandomize_from_seed(epoch_seed))
+    }
+
+    /// Same as `rerandomize_from_seed`, but the seed is kept, so the compact encoding
+    /// of the key, of its public key and of its signatures stores only the seed
+    pub fn with_randomization_seed(self, seed: GenericArray<u8, A::BlockLength>) -> Self {
+        SecretKey(self.take().with_randomization_seed(seed))
+    }
+
     pub fn randomization(
         &self,
     ) -> &GenericArray<GenericArray<u8, A::BlockLength>, A::WinternitzMinusOne> {
-        &self.0.randomization()
+        self.0.randomization()
+    }
+
+    pub fn randomization_bytes(&self) -> Vec<u8> {
+        self.0.randomization_bytes()
+    }
+
+    /// See `State::randomization_is_weak`
+    pub fn randomization_is_weak(&self) -> bool {
+        self.0.randomization_is_weak()
     }
 
     pub fn data(&self) -> &[GenericArray<u8, A::BlockLength>] {
         self.0.data()
     }
+
+    /// The randomization followed by the secret chains
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        State::from_bytes(bytes).map(SecretKey)
+    }
+
+    pub fn to_bytes_tagged(&self) -> Vec<u8> {
+        self.0.to_bytes_tagged()
+    }
+
+    /// The seed of the randomization instead of the randomization if it is known,
+    /// see `State::to_bytes_compact`
+    pub fn to_bytes_compact(&self) -> Vec<u8> {
+        self.0.to_bytes_compact()
+    }
+
+    /// The length should be exactly the one of the flag, the error is the one of `from_bytes`
+    pub fn from_bytes_compact(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match compact_lengths::<A>(bytes) {
+            Some((randomization, chains)) if bytes.len() != randomization + chains => {
+                Err(DecodeError::WrongLength {
+                    expected: randomization + chains,
+                    got: bytes.len(),
+                })
+            },
+            _ => State::from_bytes_compact(bytes).map(SecretKey),
+        }
+    }
+
+    pub fn from_bytes_tagged(bytes: &[u8]) -> Result<Self, DecodeError> {
+        State::from_bytes_tagged(bytes).map(SecretKey)
+    }
 }
 
-#[derive(Clone)]
-pub struct PublicKey<A>(Vec<GenericArray<u8, A::BlockLength>>)
+/// The randomization and the tips of the chains, exactly what the verifier needs
+pub struct PublicKey<A>(State<A>)
 where
     A: WOtsPlus;
 
+impl<A> Clone for PublicKey<A>
+where
+    A: WOtsPlus,
+{
+    fn clone(&self) -> Self {
+        PublicKey(self.0.clone())
+    }
+}
+
 impl<A> PublicKey<A>
 where
     A: WOtsPlus,
 {
     pub fn from_secret(secret_key: &SecretKey<A>) -> Self {
         match secret_key {
-            &SecretKey(ref state) => PublicKey((state * Message::infinity()).project()),
+            SecretKey(state) => PublicKey(state * Message::infinity()),
+        }
+    }
+
+    pub fn randomization(
+        &self,
+    ) -> &GenericArray<GenericArray<u8, A::BlockLength>, A::WinternitzMinusOne> {
+        self.0.randomization()
+    }
+
+    pub fn tips(&self) -> &[GenericArray<u8, A::BlockLength>] {
+        self.0.data()
+    }
+
+    /// See `State::randomization_is_weak`
+    pub fn randomization_is_weak(&self) -> bool {
+        self.0.randomization_is_weak()
+    }
+
+    /// The randomization followed by the tips
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    /// The randomization and the tips, `(WinternitzMinusOne + l1 + l2) * BlockLength`
+    pub const fn size() -> usize {
+        State::<A>::size()
+    }
+
+    /// The length should be exactly `size`, trailing bytes are rejected
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() != Self::size() {
+            return Err(DecodeError::WrongPublicKeyLength {
+                expected: Self::size(),
+                got: bytes.len(),
+            });
+        }
+        State::from_bytes(bytes).map(PublicKey)
+    }
+
+    pub fn to_bytes_tagged(&self) -> Vec<u8> {
+        self.0.to_bytes_tagged()
+    }
+
+    pub fn to_bytes_compact(&self) -> Vec<u8> {
+        self.0.to_bytes_compact()
+    }
+
+    /// The length should be exactly the one of the flag, the error is the one of `from_bytes`
+    pub fn from_bytes_compact(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match compact_lengths::<A>(bytes) {
+            Some((randomization, chains)) if bytes.len() != randomization + chains => {
+                Err(DecodeError::WrongPublicKeyLength {
+                    expected: randomization + chains,
+                    got: bytes.len(),
+                })
+            },
+            _ => State::from_bytes_compact(bytes).map(PublicKey),
         }
     }
+
+    pub fn from_bytes_tagged(bytes: &[u8]) -> Result<Self, DecodeError> {
+        ParamHeader::strip::<A>(bytes).and_then(Self::from_bytes)
+    }
+
+    /// Compress the public key into a single leaf of an xmss tree,
+    /// the compression is an L-tree, the unbalanced tree built by `collapse`
+    pub fn leaf<F>(&self, f: &F) -> GenericArray<u8, A::BlockLength>
+    where
+        F: XmssOperation<GenericArray<u8, A::BlockLength>>,
+    {
+        XmssTree(self.0.data().to_vec()).collapse(f)
+    }
 }
 
-#[derive(Clone)]
 pub struct Signature<A>(State<A>)
 where
     A: WOtsPlus;
 
+impl<A> Clone for Signature<A>
+where
+    A: WOtsPlus,
+{
+    fn clone(&self) -> Self {
+        Signature(self.0.clone())
+    }
+}
+
 impl<A> Signature<A>
 where
     A: WOtsPlus,
-    State<A>: Eq,
 {
+    /// The address is not serialized, the decoded signature of an addressed key
+    /// should get the same address before it is verified
+    pub fn with_address(self, public_seed: GenericArray<u8, A::BlockLength>, leaf: u64) -> Self {
+        Signature(self.0.with_address(public_seed, leaf))
+    }
+
     pub fn sign(secret_key: &SecretKey<A>, message: GenericArray<u8, A::MessageSize>) -> Self {
         match secret_key {
-            &SecretKey(ref state) => Signature(state * Message::message(message)),
+            SecretKey(state) => Signature(state * Message::message(message)),
+        }
+    }
+
+    /// Same as `sign`, but the signature is verified against the public key of the secret
+    /// key before it is released, `None` if it does not verify, e.g. a hardware fault
+    /// corrupted a chain, it takes about twice the time of `sign`, as all chains
+    /// are run to the tips
+    pub fn sign_redundant(
+        secret_key: &SecretKey<A>,
+        public_key: &PublicKey<A>,
+        message: GenericArray<u8, A::MessageSize>,
+    ) -> Option<Self> {
+        let signature = Self::sign(secret_key, message.clone());
+        match signature.verify(public_key, message) {
+            true => Some(signature),
+            false => None,
+        }
+    }
+
+    /// Sign the prepared message, e.g. `Message::digest` or `Message::with_prefix`
+    pub fn sign_message(secret_key: &SecretKey<A>, message: Message<A>) -> Self {
+        match secret_key {
+            SecretKey(state) => Signature(state * message),
         }
     }
 
@@ -66,19 +280,986 @@ where
         public_key: &PublicKey<A>,
         message: GenericArray<u8, A::MessageSize>,
     ) -> bool {
-        let state = match self {
-            &Signature(ref state) => state * Message::message(message).inverse(),
-        };
-        public_key.0 == state.project()
+        self.verify_message(public_key, Message::message(message))
+    }
+
+    /// Same result as `verify`, it advances all chains to the candidate public key
+    /// and compares its bytes, the randomization and the tips, by `ct_eq`, use it whenever
+    /// the timing can be observed by the one who crafted the signature
+    pub fn verify_ct(
+        &self,
+        public_key: &PublicKey<A>,
+        message: GenericArray<u8, A::MessageSize>,
+    ) -> bool {
+        ct_eq(
+            &self.recover_public_key(message).to_bytes(),
+            &public_key.to_bytes(),
+        )
+    }
+
+    /// Same result as `verify`, but stops at the first chain that does not match,
+    /// faster to reject the garbage, the timing reveals the failed chain,
+    /// so use it only where nobody can measure the time, e.g. for the local data
+    pub fn verify_fast(
+        &self,
+        public_key: &PublicKey<A>,
+        message: GenericArray<u8, A::MessageSize>,
+    ) -> bool {
+        self.0.randomization() == public_key.0.randomization()
+            && self
+                .0
+                .tips_match_fast(&Message::message(message).inverse(), public_key.0.data())
+    }
+
+    /// Verify the signature of the prepared message, it should be constructed
+    /// the same way as the signed one, the randomization of the signature
+    /// should be the one of the public key
+    pub fn verify_message(&self, public_key: &PublicKey<A>, message: Message<A>) -> bool {
+        self.0.randomization() == public_key.0.randomization()
+            && self.0.tips_match(&message.inverse(), public_key.0.data())
+    }
+
+    /// Same as `verify`, also the number of the digest invocations it performed,
+    /// `w - 1 - digit` for each chain, twice as much for the addressed chains
+    pub fn verify_counted(
+        &self,
+        public_key: &PublicKey<A>,
+        message: GenericArray<u8, A::MessageSize>,
+    ) -> (bool, usize) {
+        let message = Message::message(message).inverse();
+        if self.0.randomization() != public_key.0.randomization()
+            || self.0.data().len() != public_key.0.data().len()
+        {
+            return (false, 0);
+        }
+        let calls = self.0.hash_calls(&message);
+        (self.0.tips_match(&message, public_key.0.data()), calls)
+    }
+
+    /// The candidate public key, advance the chains to the tips,
+    /// for a valid signature it is the public key of the signer
+    pub fn recover_public_key(&self, message: GenericArray<u8, A::MessageSize>) -> PublicKey<A> {
+        match self {
+            Signature(state) => PublicKey(state * Message::message(message).inverse()),
+        }
     }
 
     pub fn randomization(
         &self,
     ) -> &GenericArray<GenericArray<u8, A::BlockLength>, A::WinternitzMinusOne> {
-        &self.0.randomization()
+        self.0.randomization()
+    }
+
+    pub fn randomization_bytes(&self) -> Vec<u8> {
+        self.0.randomization_bytes()
     }
 
     pub fn data(&self) -> &[GenericArray<u8, A::BlockLength>] {
         self.0.data()
     }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    /// The length should be exactly the randomization and the chains,
+    /// `MalformedSignature` tells the expected length of each
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (l1, l2) = State::<A>::lengths();
+        let n = A::BlockLength::USIZE;
+        if bytes.len() != State::<A>::size() {
+            return Err(DecodeError::MalformedSignature {
+                expected_randomization: A::WinternitzMinusOne::USIZE * n,
+                expected_chains: (l1 + l2) * n,
+                got: bytes.len(),
+            });
+        }
+        State::from_bytes(bytes).map(Signature)
+    }
+
+    pub fn to_bytes_tagged(&self) -> Vec<u8> {
+        self.0.to_bytes_tagged()
+    }
+
+    pub fn to_bytes_compact(&self) -> Vec<u8> {
+        self.0.to_bytes_compact()
+    }
+
+    /// The length should be exactly the one of the flag, the error is the one of `from_bytes`
+    pub fn from_bytes_compact(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match compact_lengths::<A>(bytes) {
+            Some((randomization, chains)) if bytes.len() != randomization + chains => {
+                Err(DecodeError::MalformedSignature {
+                    expected_randomization: randomization,
+                    expected_chains: chains,
+                    got: bytes.len(),
+                })
+            },
+            _ => State::from_bytes_compact(bytes).map(Signature),
+        }
+    }
+
+    pub fn from_bytes_tagged(bytes: &[u8]) -> Result<Self, DecodeError> {
+        ParamHeader::strip::<A>(bytes).and_then(Self::from_bytes)
+    }
+}
+
+// the lengths of the randomization with the flag and of the chains in the compact
+// encoding, the seed is expected if there is no flag, `None` for an unknown flag
+fn compact_lengths<A>(bytes: &[u8]) -> Option<(usize, usize)>
+where
+    A: WOtsPlus,
+{
+    let (l1, l2) = State::<A>::lengths();
+    let n = A::BlockLength::USIZE;
+    match bytes.first() {
+        Some(&0) => Some((1 + A::WinternitzMinusOne::USIZE * n, (l1 + l2) * n)),
+        None | Some(&1) => Some((1 + n, (l1 + l2) * n)),
+        Some(_) => None,
+    }
+}
+
+/// Whether the public key is the one of the secret key, compared in constant time,
+/// e.g. to detect corrupted or mismatched files on load
+pub fn key_pair_matches<A>(secret_key: &SecretKey<A>, public_key: &PublicKey<A>) -> bool
+where
+    A: WOtsPlus,
+{
+    ct_eq(
+        &PublicKey::from_secret(secret_key).to_bytes(),
+        &public_key.to_bytes(),
+    )
+}
+
+/// Advance and compare only the checksum chains, a quick check of the region before
+/// the full verification, a valid signature always passes it, but it proves nothing
+pub fn verify_checksum_chains<A>(
+    public_key: &PublicKey<A>,
+    message: GenericArray<u8, A::MessageSize>,
+    signature: &Signature<A>,
+) -> bool
+where
+    A: WOtsPlus,
+{
+    signature.0.randomization() == public_key.0.randomization()
+        && signature.0.tips_match_in(
+            &Message::message(message).inverse(),
+            public_key.0.data(),
+            Message::<A>::checksum_chains(),
+        )
+}
+
+/// The indices of the chains whose recovered tip differs from the public key,
+/// a wrong randomization fails all chains, a debugging tool to tell scattered corruption
+/// from targeted tampering, it is not constant time, use `verify` otherwise
+pub fn verify_detailed<A>(
+    public_key: &PublicKey<A>,
+    message: GenericArray<u8, A::MessageSize>,
+    signature: &Signature<A>,
+) -> Result<(), Vec<usize>>
+where
+    A: WOtsPlus,
+{
+    let recovered = signature.recover_public_key(message);
+    let failed = match recovered.randomization() == public_key.randomization() {
+        false => (0..public_key.tips().len()).collect(),
+        true => recovered
+            .tips()
+            .iter()
+            .zip(public_key.tips())
+            .enumerate()
+            .filter(|&(_, (a, b))| a != b)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>(),
+    };
+    match failed.is_empty() {
+        true => Ok(()),
+        false => Err(failed),
+    }
+}
+
+/// Verify with the message decomposed once, so verifying the same message
+/// against many keys does not repeat the decomposition and the checksum
+pub fn verify_with_message<A>(
+    public_key: &PublicKey<A>,
+    message: &Message<A>,
+    signature: &Signature<A>,
+) -> bool
+where
+    A: WOtsPlus,
+{
+    signature.verify_message(public_key, message.clone())
+}
+
+/// Decode the tagged public key and signature and verify, the header carries the hash id,
+/// so the objects of another hash, e.g. truncated SHA-512 where `A` is SHA-256,
+/// are rejected with `ParamMismatch` rather than failing as a wrong signature,
+/// the parameters with `HashId::Unspecified` cannot tell the hashes apart
+pub fn verify_tagged<A>(
+    public_key: &[u8],
+    message: GenericArray<u8, A::MessageSize>,
+    signature: &[u8],
+) -> Result<bool, DecodeError>
+where
+    A: WOtsPlus,
+{
+    let public_key = PublicKey::<A>::from_bytes_tagged(public_key)?;
+    let signature = Signature::<A>::from_bytes_tagged(signature)?;
+    Ok(signature.verify(&public_key, message))
+}
+
+/// Verify the chains of the signature, the randomization is expanded from the seed,
+/// see `State::rerandomize_from_seed`, and each row is derived when the chains need it,
+/// so the matrix is never stored, it takes a hash per step more than `verify`,
+/// the chains bound to an address are not supported
+pub fn verify_lazy<A>(
+    public_key_tips: &[GenericArray<u8, A::BlockLength>],
+    message: GenericArray<u8, A::MessageSize>,
+    signature_chains: &[GenericArray<u8, A::BlockLength>],
+    matrix_seed: &[u8],
+) -> bool
+where
+    A: WOtsPlus,
+{
+    use digest::{generic_array::sequence::GenericSequence, Digest};
+
+    let message = Message::<A>::message(message).inverse();
+    if public_key_tips.len() != message.ranges().len()
+        || signature_chains.len() != message.ranges().len()
+    {
+        return false;
+    }
+
+    let difference = signature_chains
+        .iter()
+        .zip(message.ranges())
+        .zip(public_key_tips)
+        .fold(0, |difference, ((block, range), expected)| {
+            let tip = range.clone().fold(block.clone(), |b, j| {
+                let a = seed::expand_keyed_block::<A>(matrix_seed, j as u32);
+                let v = GenericArray::<u8, A::BlockLength>::generate(|i| a[i] ^ b[i]);
+                A::Digest::new().chain(v).result()
+            });
+            tip.iter()
+                .zip(expected.iter())
+                .fold(difference, |difference, (a, b)| difference | (a ^ b))
+        });
+    difference == 0
+}
+
+/// Verify the chains of the signature with the randomization shared by the whole tree,
+/// stored once, so the serialized public keys and signatures can omit it,
+/// the chains bound to an address are not supported
+pub fn verify_with_shared_randomization<A>(
+    shared: &GenericArray<Block<A>, A::WinternitzMinusOne>,
+    public_key_tips: &[Block<A>],
+    message: GenericArray<u8, A::MessageSize>,
+    signature_chains: &[Block<A>],
+) -> bool
+where
+    A: WOtsPlus,
+{
+    let message = Message::<A>::message(message).inverse();
+    if public_key_tips.len() != message.ranges().len()
+        || signature_chains.len() != message.ranges().len()
+    {
+        return false;
+    }
+
+    let difference = signature_chains
+        .iter()
+        .zip(message.ranges())
+        .zip(public_key_tips)
+        .fold(0, |difference, ((block, range), expected)| {
+            let tip = chain_advance::<A>(block, shared, range.start, range.len());
+            tip.iter()
+                .zip(expected.iter())
+                .fold(difference, |difference, (a, b)| difference | (a ^ b))
+        });
+    difference == 0
+}
+
+/// The chain index, the digit of the message, the block of the signature and the block
+/// of the public key for each chain, to see where a failed verification diverges
+pub fn align<'a, A>(
+    message: &Message<A>,
+    signature: &'a Signature<A>,
+    public_key: &'a PublicKey<A>,
+) -> impl Iterator<Item = (usize, usize, &'a Block<A>, &'a Block<A>)>
+where
+    A: WOtsPlus,
+{
+    message
+        .ranges()
+        .iter()
+        .map(|range| range.end)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .zip(signature.data().iter().zip(public_key.tips()))
+        .enumerate()
+        .map(|(index, (digit, (block, tip)))| (index, digit, block, tip))
+}
+
+/// Sign the digits without any seed derivation or message hashing, the digits are
+/// in the chain order, the checksum included, it is what the test vectors fix
+pub fn sign_raw<A>(
+    secret_data: Vec<GenericArray<u8, A::BlockLength>>,
+    randomization: GenericArray<GenericArray<u8, A::BlockLength>, A::WinternitzMinusOne>,
+    digits: &[u8],
+) -> Signature<A>
+where
+    A: WOtsPlus,
+{
+    let (l1, l2) = State::<A>::lengths();
+    assert_eq!(
+        digits.len(),
+        l1 + l2,
+        "the number of digits should be `l1 + l2`"
+    );
+    let message = Message::from_digits(digits).unwrap_or_else(|error| panic!("{}", error));
+    let state = State::new(randomization, secret_data);
+    Signature(&state * message)
+}
+
+/// Sign the root of the tree below, as the keys of the upper trees of a hypertree do,
+/// the root is mapped to `MessageSize` bytes by `fit_digest`, so it is kept as is
+/// if the sizes are equal
+pub fn sign_root<A>(secret_key: &SecretKey<A>, root: &Block<A>) -> Signature<A>
+where
+    A: WOtsPlus,
+{
+    Signature::sign(secret_key, fit_digest::<A>(root.as_ref()))
+}
+
+/// Verify the signature made by `sign_root`
+pub fn verify_root<A>(public_key: &PublicKey<A>, root: &Block<A>, signature: &Signature<A>) -> bool
+where
+    A: WOtsPlus,
+{
+    signature.verify(public_key, fit_digest::<A>(root.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use digest::{
+        generic_array::{
+            GenericArray,
+            typenum::{U16, U32, U15},
+        },
+        Digest,
+    };
+    use super::super::{
+        sha256::Sha256, WOtsPlus, Message, DecodeError, ChecksumPlacement, serialized_len, HashId,
+        ParamHeader,
+    };
+    use super::{
+        SecretKey, PublicKey, Signature, key_pair_matches, verify_checksum_chains, verify_detailed,
+        verify_with_message, verify_tagged, verify_lazy, verify_with_shared_randomization, align,
+        sign_raw, sign_root, verify_root,
+    };
+
+    type A = (U32, U32, U15, Sha256, ());
+
+    fn secret_key() -> SecretKey<A> {
+        SecretKey::from_seed(&Sha256::digest(b"seed"))
+    }
+
+    #[test]
+    fn test_sign_root() {
+        fn check<A>()
+        where
+            A: WOtsPlus<BlockLength = U32, Digest = Sha256>,
+        {
+            let secret_key = SecretKey::<A>::from_seed(&Sha256::digest(b"seed"));
+            let public_key = PublicKey::from_secret(&secret_key);
+            let root = Sha256::digest(b"root");
+            let signature = sign_root(&secret_key, &root);
+            assert!(verify_root(&public_key, &root, &signature));
+            assert!(!verify_root(
+                &public_key,
+                &Sha256::digest(b"other"),
+                &signature
+            ));
+        }
+
+        check::<(U32, U32, U15, Sha256, ())>();
+        check::<(U32, U16, U15, Sha256, ())>();
+
+        let secret_key = secret_key();
+        let root = Sha256::digest(b"root");
+        assert_eq!(
+            sign_root(&secret_key, &root).to_bytes(),
+            Signature::sign(&secret_key, root).to_bytes(),
+        );
+    }
+
+    #[test]
+    fn test_verify_detailed() {
+        let secret_key = secret_key();
+        let public_key = PublicKey::from_secret(&secret_key);
+        let message = Sha256::digest(b"message");
+        let signature = Signature::sign(&secret_key, message);
+        assert_eq!(verify_detailed(&public_key, message, &signature), Ok(()));
+
+        let mut bytes = signature.to_bytes();
+        let chains = bytes.len() - 67 * 32;
+        bytes[chains + 3 * 32] ^= 1;
+        bytes[chains + 40 * 32 + 7] ^= 0x80;
+        let corrupted = Signature::<A>::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            verify_detailed(&public_key, message, &corrupted),
+            Err(vec![3, 40]),
+        );
+
+        let other = PublicKey::from_secret(&SecretKey::<A>::from_seed(&Sha256::digest(b"other")));
+        assert_eq!(
+            verify_detailed(&other, message, &signature),
+            Err((0..67).collect()),
+        );
+    }
+
+    #[test]
+    fn test_verify_with_shared_randomization() {
+        let secret_key = secret_key();
+        let shared = *secret_key.randomization();
+        let other = SecretKey::<A>::new(
+            shared,
+            SecretKey::<A>::from_seed(&Sha256::digest(b"other"))
+                .data()
+                .to_vec(),
+        );
+        let message = Sha256::digest(b"message");
+        for secret_key in &[secret_key, other] {
+            let public_key = PublicKey::from_secret(secret_key);
+            let signature = Signature::sign(secret_key, message);
+            assert!(signature.verify(&public_key, message));
+            assert!(verify_with_shared_randomization::<A>(
+                &shared,
+                public_key.tips(),
+                message,
+                signature.data(),
+            ));
+            assert!(!verify_with_shared_randomization::<A>(
+                &shared,
+                public_key.tips(),
+                Sha256::digest(b"other"),
+                signature.data(),
+            ));
+            assert!(!verify_with_shared_randomization::<A>(
+                &shared,
+                &public_key.tips()[1..],
+                message,
+                signature.data(),
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_fast() {
+        let secret_key = secret_key();
+        let public_key = PublicKey::from_secret(&secret_key);
+        let message = Sha256::digest(b"message");
+        let signature = Signature::sign(&secret_key, message);
+        assert!(signature.verify_ct(&public_key, message));
+        assert!(signature.verify_fast(&public_key, message));
+
+        let other = Sha256::digest(b"other");
+        assert!(!signature.verify_ct(&public_key, other));
+        assert!(!signature.verify_fast(&public_key, other));
+
+        let chains = signature.to_bytes().len() - 67 * 32;
+        for &position in &[0, chains, chains + 66 * 32 + 31] {
+            let mut bytes = signature.to_bytes();
+            bytes[position] ^= 1;
+            let corrupted = Signature::<A>::from_bytes(&bytes).unwrap();
+            assert!(!corrupted.verify_ct(&public_key, message));
+            assert!(!corrupted.verify_fast(&public_key, message));
+        }
+
+        let bound = secret_key.with_address(Sha256::digest(b"public seed"), 3);
+        let signature = Signature::sign(&bound, message);
+        assert!(signature.verify_ct(&PublicKey::from_secret(&bound), message));
+        assert!(!signature.verify_ct(&public_key, message));
+    }
+
+    #[test]
+    fn test_sign_redundant() {
+        let secret_key = secret_key();
+        let public_key = PublicKey::from_secret(&secret_key);
+        let message = Sha256::digest(b"message");
+        let signature = Signature::sign_redundant(&secret_key, &public_key, message).unwrap();
+        assert_eq!(
+            signature.to_bytes(),
+            Signature::sign(&secret_key, message).to_bytes()
+        );
+
+        // a fault in a secret chain gives a signature of the other key
+        let mut data = secret_key.data().to_vec();
+        data[5][0] ^= 1;
+        let faulty = SecretKey::<A>::new(*secret_key.randomization(), data);
+        assert!(Signature::sign_redundant(&faulty, &public_key, message).is_none());
+    }
+
+    #[test]
+    fn test_sign_raw() {
+        let secret_key = secret_key();
+        let message = Sha256::digest(b"message");
+        let digits = Message::<A>::message(message)
+            .explain()
+            .into_iter()
+            .map(|d| d as u8)
+            .collect::<Vec<_>>();
+        let signature = sign_raw::<A>(
+            secret_key.data().to_vec(),
+            *secret_key.randomization(),
+            &digits,
+        );
+        assert_eq!(
+            signature.to_bytes(),
+            Signature::sign(&secret_key, message).to_bytes()
+        );
+        assert!(signature.verify(&PublicKey::from_secret(&secret_key), message));
+
+        let zeros = sign_raw::<A>(
+            secret_key.data().to_vec(),
+            *secret_key.randomization(),
+            &[0; 67],
+        );
+        assert_eq!(zeros.data(), secret_key.data());
+    }
+
+    #[test]
+    fn test_verify_checksum_chains() {
+        struct Prepend;
+
+        impl WOtsPlus for Prepend {
+            type BlockLength = U32;
+            type MessageSize = U32;
+            type WinternitzMinusOne = U15;
+            type Digest = Sha256;
+
+            const CHECKSUM_PLACEMENT: ChecksumPlacement = ChecksumPlacement::Prepend;
+        }
+
+        fn check<A>(checksum: usize)
+        where
+            A: WOtsPlus<BlockLength = U32, MessageSize = U32, Digest = Sha256>,
+        {
+            let secret_key = SecretKey::<A>::from_seed(&Sha256::digest(b"seed"));
+            let public_key = PublicKey::from_secret(&secret_key);
+            let message = Sha256::digest(b"message");
+            let signature = Signature::sign(&secret_key, message);
+            assert!(signature.verify(&public_key, message));
+            assert!(verify_checksum_chains(&public_key, message, &signature));
+
+            let corrupt = |chain: usize| {
+                let mut bytes = signature.to_bytes();
+                bytes[(15 + chain) * 32] ^= 1;
+                Signature::<A>::from_bytes(&bytes).unwrap()
+            };
+            assert!(!verify_checksum_chains(
+                &public_key,
+                message,
+                &corrupt(checksum)
+            ));
+            let other = if checksum == 0 { 66 } else { 0 };
+            assert!(verify_checksum_chains(
+                &public_key,
+                message,
+                &corrupt(other)
+            ));
+            assert!(!corrupt(other).verify(&public_key, message));
+        }
+
+        check::<(U32, U32, U15, Sha256, ())>(65);
+        check::<Prepend>(0);
+    }
+
+    #[test]
+    fn test_align() {
+        let secret_key = secret_key();
+        let public_key = PublicKey::from_secret(&secret_key);
+        let message = Message::<A>::digest(b"message");
+        let signature = Signature::sign_message(&secret_key, message.clone());
+
+        let aligned = align(&message, &signature, &public_key).collect::<Vec<_>>();
+        assert_eq!(aligned.len(), 67);
+        for (&(index, digit, block, tip), explained) in aligned.iter().zip(message.explain()) {
+            assert_eq!(digit, explained);
+            assert_eq!(block, &signature.data()[index]);
+            assert_eq!(tip, &public_key.tips()[index]);
+        }
+    }
+
+    #[test]
+    fn test_bytes_compact() {
+        let secret_key = SecretKey::<A>::from_seed(&Sha256::digest(b"seed"))
+            .with_randomization_seed(Sha256::digest(b"epoch"));
+        let public_key = PublicKey::from_secret(&secret_key);
+        let message = Sha256::digest(b"message");
+        let signature = Signature::sign(&secret_key, message);
+
+        let bytes = public_key.to_bytes_compact();
+        assert_eq!(bytes.len(), 1 + (1 + 67) * 32);
+        let public_key = PublicKey::<A>::from_bytes_compact(&bytes).unwrap();
+        let bytes = signature.to_bytes_compact();
+        assert_eq!(bytes.len(), 1 + (1 + 67) * 32);
+        let signature = Signature::<A>::from_bytes_compact(&bytes).unwrap();
+        assert!(signature.verify(&public_key, message));
+
+        let bytes = secret_key.to_bytes_compact();
+        let decoded = SecretKey::<A>::from_bytes_compact(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), secret_key.to_bytes());
+
+        let seeded = 1 + (1 + 67) * 32;
+        let full = 1 + (15 + 67) * 32;
+        assert_eq!(
+            SecretKey::<A>::from_bytes_compact(&bytes[..100]).err(),
+            Some(DecodeError::WrongLength {
+                expected: seeded,
+                got: 100,
+            }),
+        );
+        assert_eq!(
+            PublicKey::<A>::from_bytes_compact(&[]).err(),
+            Some(DecodeError::WrongPublicKeyLength {
+                expected: seeded,
+                got: 0,
+            }),
+        );
+        let mut bytes = signature.to_bytes_compact();
+        bytes.push(0);
+        assert_eq!(
+            Signature::<A>::from_bytes_compact(&bytes).err(),
+            Some(DecodeError::MalformedSignature {
+                expected_randomization: 1 + 32,
+                expected_chains: 67 * 32,
+                got: seeded + 1,
+            }),
+        );
+        bytes[0] = 0;
+        assert_eq!(
+            Signature::<A>::from_bytes_compact(&bytes).err(),
+            Some(DecodeError::MalformedSignature {
+                expected_randomization: 1 + 15 * 32,
+                expected_chains: 67 * 32,
+                got: seeded + 1,
+            }),
+        );
+        let mut bytes = vec![0];
+        bytes.extend_from_slice(&signature.to_bytes());
+        assert_eq!(bytes.len(), full);
+        assert!(Signature::<A>::from_bytes_compact(&bytes).is_ok());
+        bytes[0] = 2;
+        assert_eq!(
+            Signature::<A>::from_bytes_compact(&bytes).err(),
+            Some(DecodeError::UnknownRandomizationFlag { flag: 2 }),
+        );
+    }
+
+    #[test]
+    fn test_verify_counted() {
+        let secret_key = secret_key();
+        let public_key = PublicKey::from_secret(&secret_key);
+        let message = Sha256::digest(b"message");
+        let signature = Signature::sign(&secret_key, message);
+        let expected = Message::<A>::message(message)
+            .explain()
+            .into_iter()
+            .map(|digit| 15 - digit)
+            .sum::<usize>();
+        assert_eq!(
+            signature.verify_counted(&public_key, message),
+            (true, expected)
+        );
+
+        let other = Sha256::digest(b"other");
+        let (valid, _) = signature.verify_counted(&public_key, other);
+        assert!(!valid);
+
+        let public_seed = Sha256::digest(b"public seed");
+        let secret_key = secret_key.with_address(public_seed, 3);
+        let public_key = PublicKey::from_secret(&secret_key);
+        let signature = Signature::sign(&secret_key, message);
+        assert_eq!(
+            signature.verify_counted(&public_key, message),
+            (true, 2 * expected)
+        );
+    }
+
+    #[test]
+    fn test_signature_bytes() {
+        let secret_key = secret_key();
+        let bytes = Signature::sign(&secret_key, Sha256::digest(b"message")).to_bytes();
+        let malformed = |got: usize| {
+            Some(DecodeError::MalformedSignature {
+                expected_randomization: 15 * 32,
+                expected_chains: 67 * 32,
+                got,
+            })
+        };
+        assert!(Signature::<A>::from_bytes(&bytes).is_ok());
+        assert_eq!(
+            Signature::<A>::from_bytes(&bytes[32..]).err(),
+            malformed(bytes.len() - 32),
+        );
+        let mut long = bytes.clone();
+        long.extend_from_slice(&[0; 32]);
+        assert_eq!(
+            Signature::<A>::from_bytes(&long).err(),
+            malformed(bytes.len() + 32),
+        );
+        let mut tagged = Signature::<A>::from_bytes(&bytes)
+            .unwrap()
+            .to_bytes_tagged();
+        tagged.pop();
+        assert_eq!(
+            Signature::<A>::from_bytes_tagged(&tagged).err(),
+            malformed(bytes.len() - 1),
+        );
+    }
+
+    #[test]
+    fn test_sign_message() {
+        type A = (U32, U16, U15, Sha256, ());
+
+        let secret_key = SecretKey::<A>::from_seed(&Sha256::digest(b"seed"));
+        let public_key = PublicKey::from_secret(&secret_key);
+
+        let signature =
+            Signature::sign_message(&secret_key, Message::with_prefix(b"context", b"data"));
+        assert!(signature.verify_message(&public_key, Message::with_prefix(b"context", b"data")));
+        assert!(signature.verify_message(&public_key, Message::digest(b"contextdata")));
+        assert!(!signature.verify_message(&public_key, Message::digest(b"data")));
+        assert!(!signature.verify_message(&public_key, Message::with_prefix(b"other", b"data")));
+
+        let truncated = GenericArray::clone_from_slice(&Sha256::digest(b"contextdata")[..16]);
+        assert!(signature.verify(&public_key, truncated));
+
+        let randomization = signature.randomization_bytes();
+        assert_eq!(randomization, secret_key.randomization_bytes());
+        assert_eq!(randomization.len(), 15 * 32);
+        assert_eq!(randomization[32..64], signature.randomization()[1][..]);
+        assert!(signature.to_bytes().starts_with(&randomization));
+    }
+
+    #[test]
+    fn test_verify_with_message() {
+        let message_bytes = Sha256::digest(b"message");
+        let message = Message::<A>::message(message_bytes);
+        for i in 0..3u8 {
+            let secret_key = SecretKey::<A>::from_seed(&Sha256::digest(&[i]));
+            let public_key = PublicKey::from_secret(&secret_key);
+            let signature = Signature::sign(&secret_key, message_bytes);
+            let other = Signature::sign(&secret_key, Sha256::digest(b"other"));
+            assert!(verify_with_message(&public_key, &message, &signature));
+            assert_eq!(
+                verify_with_message(&public_key, &message, &other),
+                other.verify(&public_key, message_bytes),
+            );
+        }
+    }
+
+    #[test]
+    fn test_tips_match() {
+        let secret_key = secret_key();
+        let public_key = PublicKey::from_secret(&secret_key);
+        let message = Sha256::digest(b"message");
+        let signature = Signature::sign(&secret_key, message);
+
+        for &m in &[message, Sha256::digest(b"other")] {
+            let allocating = public_key.tips() == signature.recover_public_key(m).tips();
+            assert_eq!(signature.verify(&public_key, m), allocating);
+            assert_eq!(
+                signature
+                    .0
+                    .tips_match(&Message::message(m).inverse(), public_key.tips()),
+                allocating,
+            );
+        }
+        assert!(signature.verify(&public_key, message));
+        assert!(!signature.0.tips_match(
+            &Message::message(message).inverse(),
+            &public_key.tips()[1..]
+        ));
+    }
+
+    #[test]
+    fn test_public_key_bytes() {
+        let secret_key = secret_key();
+        let public_key = PublicKey::from_secret(&secret_key);
+        let bytes = public_key.to_bytes();
+        assert_eq!(bytes.len(), serialized_len::<A>().public_key);
+        assert!(bytes.starts_with(&secret_key.randomization_bytes()));
+
+        let decoded = PublicKey::<A>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.tips(), public_key.tips());
+        assert_eq!(PublicKey::<A>::size(), (15 + 67) * 32);
+        assert_eq!(
+            PublicKey::<A>::from_bytes(&bytes[1..]).err(),
+            Some(DecodeError::WrongPublicKeyLength {
+                expected: (15 + 67) * 32,
+                got: (15 + 67) * 32 - 1,
+            }),
+        );
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(
+            PublicKey::<A>::from_bytes(&long).err(),
+            Some(DecodeError::WrongPublicKeyLength {
+                expected: (15 + 67) * 32,
+                got: (15 + 67) * 32 + 1,
+            }),
+        );
+        assert_eq!(decoded.randomization(), public_key.randomization());
+
+        let message = Sha256::digest(b"message");
+        let signature = Signature::sign(&secret_key, message);
+        assert!(signature.verify(&decoded, message));
+
+        let other = PublicKey::from_secret(&SecretKey::<A>::from_seed(&Sha256::digest(b"other")));
+        let mut bytes = other.to_bytes();
+        bytes[(15 * 32)..].copy_from_slice(&public_key.to_bytes()[(15 * 32)..]);
+        let mixed = PublicKey::<A>::from_bytes(&bytes).unwrap();
+        assert!(!signature.verify(&mixed, message));
+    }
+
+    #[test]
+    fn test_rerandomize_from_seed() {
+        let secret_key = secret_key();
+        let public_key = PublicKey::from_secret(&secret_key);
+        let epoch = secret_key.clone().rerandomize_from_seed(b"epoch 1");
+        assert_eq!(epoch.data(), secret_key.data());
+        assert_ne!(epoch.randomization(), secret_key.randomization());
+
+        let epoch_public_key = PublicKey::from_secret(&epoch);
+        let again = PublicKey::from_secret(&secret_key.clone().rerandomize_from_seed(b"epoch 1"));
+        assert_eq!(again.to_bytes(), epoch_public_key.to_bytes());
+        assert_ne!(epoch_public_key.tips(), public_key.tips());
+
+        let message = Sha256::digest(b"message");
+        let signature = Signature::sign(&epoch, message);
+        assert!(signature.verify(&epoch_public_key, message));
+        assert!(!signature.verify(&public_key, message));
+        assert!(!Signature::sign(&secret_key, message).verify(&epoch_public_key, message));
+    }
+
+    #[test]
+    fn test_recover_public_key() {
+        let secret_key = secret_key();
+        let public_key = PublicKey::from_secret(&secret_key);
+        let message = Sha256::digest(b"message");
+        let signature = Signature::sign(&secret_key, message);
+
+        let recovered = signature.recover_public_key(message);
+        assert_eq!(recovered.to_bytes(), public_key.to_bytes());
+        let other = signature.recover_public_key(Sha256::digest(b"other"));
+        assert_eq!(other.randomization(), public_key.randomization());
+        assert_ne!(other.tips(), public_key.tips());
+    }
+
+    #[test]
+    fn test_verify_lazy() {
+        let secret_key =
+            SecretKey::<A>::from_seed(&Sha256::digest(b"seed")).rerandomize_from_seed(b"matrix");
+        let public_key = PublicKey::from_secret(&secret_key);
+        let message = Sha256::digest(b"message");
+        let other = Sha256::digest(b"other");
+        let signature = Signature::sign(&secret_key, message);
+
+        for &m in &[message, other] {
+            assert_eq!(
+                verify_lazy::<A>(public_key.tips(), m, signature.data(), b"matrix"),
+                signature.verify(&public_key, m),
+            );
+        }
+        assert!(verify_lazy::<A>(
+            public_key.tips(),
+            message,
+            signature.data(),
+            b"matrix"
+        ));
+        assert!(!verify_lazy::<A>(
+            public_key.tips(),
+            message,
+            signature.data(),
+            b"other"
+        ));
+        assert!(!verify_lazy::<A>(
+            &public_key.tips()[1..],
+            message,
+            signature.data(),
+            b"matrix"
+        ));
+    }
+
+    #[test]
+    fn test_key_pair_matches() {
+        let secret_key = secret_key();
+        let public_key = PublicKey::from_secret(&secret_key);
+        assert!(key_pair_matches(&secret_key, &public_key));
+
+        let other = SecretKey::<A>::from_seed(&Sha256::digest(b"other"));
+        assert!(!key_pair_matches(&other, &public_key));
+
+        let mut bytes = public_key.to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(!key_pair_matches(
+            &secret_key,
+            &PublicKey::from_bytes(&bytes).unwrap()
+        ));
+        let mut bytes = public_key.to_bytes();
+        bytes[0] ^= 1;
+        assert!(!key_pair_matches(
+            &secret_key,
+            &PublicKey::from_bytes(&bytes).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_verify_tagged() {
+        struct Sha256Params;
+
+        impl WOtsPlus for Sha256Params {
+            type BlockLength = U32;
+            type MessageSize = U32;
+            type WinternitzMinusOne = U15;
+            type Digest = Sha256;
+
+            const HASH_ID: HashId = HashId::Sha256;
+        }
+
+        // the same sizes, declared as truncated SHA-512
+        struct Truncated;
+
+        impl WOtsPlus for Truncated {
+            type BlockLength = U32;
+            type MessageSize = U32;
+            type WinternitzMinusOne = U15;
+            type Digest = Sha256;
+
+            const HASH_ID: HashId = HashId::Sha512_256;
+        }
+
+        let message = Sha256::digest(b"message");
+        let secret_key = SecretKey::<Truncated>::from_seed(&Sha256::digest(b"seed"));
+        let public_key = PublicKey::from_secret(&secret_key).to_bytes_tagged();
+        let signature = Signature::sign(&secret_key, message).to_bytes_tagged();
+        assert_eq!(
+            verify_tagged::<Truncated>(&public_key, message, &signature),
+            Ok(true)
+        );
+        assert_eq!(
+            verify_tagged::<Sha256Params>(&public_key, message, &signature),
+            Err(DecodeError::ParamMismatch {
+                expected: ParamHeader::of::<Sha256Params>(),
+                got: ParamHeader::of::<Truncated>(),
+            }),
+        );
+        assert_eq!(
+            verify_tagged::<Truncated>(&public_key, Sha256::digest(b"other"), &signature),
+            Ok(false)
+        );
+    }
 }
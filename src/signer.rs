@@ -0,0 +1,602 @@
+use digest::generic_array::{GenericArray, typenum::Unsigned};
+use byteorder::{ByteOrder, BigEndian};
+
+use super::state::WOtsPlus;
+use super::signature::{SecretKey, Signature};
+use super::xmss::{self, XmssOperation, XmssPath, XmssTree};
+use super::xmss_signature::XmssSignature;
+use super::seed::{self, derive, Domain, leaf_secret_key, leaf_public_key};
+use super::error::{DecodeError, SignerError};
+use super::keypair::{XmssPublic, audit_tree};
+
+type Block<A> = GenericArray<u8, <A as WOtsPlus>::BlockLength>;
+
+/// Stateful xmss signer, derives the one time keys from the master seed,
+/// the index of the next unused key is the state.
+///
+/// The index must survive restarts, otherwise a one time key is reused.
+/// Persist before publish: `sign_next` advances the index,
+/// store `to_bytes` durably and only then release the signature.
+/// If the process crashes in between, the key is wasted, but never reused.
+///
+/// The first signature computes the path of its key by treehash, then the signer keeps
+/// the path of the next key and updates it in amortized `O(height)` leaf generations,
+/// the path is persisted too.
+#[derive(Clone)]
+pub struct Signer<A>
+where
+    A: WOtsPlus,
+{
+    master_seed: Block<A>,
+    // derived from the master seed, binds the one time keys to their leaves
+    public_seed: Block<A>,
+    height: usize,
+    index: u64,
+    // the siblings of the path of `index`, if computed
+    siblings: Option<Vec<Block<A>>>,
+}
+
+impl<A> Signer<A>
+where
+    A: WOtsPlus,
+{
+    /// Fails on the height above `MAX_HEIGHT`
+    pub fn new(master_seed: Block<A>, height: usize) -> Result<Self, SignerError> {
+        seed::check_height(height)?;
+        Ok(Signer {
+            public_seed: derive::<A>(&master_seed, Domain::PublicSeed, 0),
+            master_seed,
+            height,
+            index: 0,
+            siblings: None,
+        })
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The public seed of `XmssPublic`, each one time key is bound to its leaf by it
+    pub fn public_seed(&self) -> &Block<A> {
+        &self.public_seed
+    }
+
+    /// The index of the next unused one time key
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// The number of one time keys, `2 ^ height`
+    pub fn capacity(&self) -> u64 {
+        seed::capacity(self.height)
+    }
+
+    fn tree<F>(&self, f: &F) -> XmssTree<Block<A>>
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        XmssTree(
+            (0..self.capacity())
+                .map(|index| {
+                    leaf_public_key::<A, F>(&self.master_seed, &self.public_seed, index, f)
+                })
+                .collect(),
+        )
+    }
+
+    pub fn root<F>(&self, f: &F) -> Block<A>
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        self.tree(f).collapse(f)
+    }
+
+    /// Rebuild the tree and compare its root with the public key, also check
+    /// the kept path of the next key leads to it, run it on load before signing,
+    /// so the corrupted or mismatched state never signs under a key that does not verify
+    pub fn self_check<F>(&self, public: &XmssPublic<A>, f: &F) -> Result<(), SignerError>
+    where
+        F: XmssOperation<Block<A>> + Sync,
+    {
+        let root = audit_tree::<A, F>(&self.master_seed, public.public_seed(), self.height, f)?;
+        if &root != public.root() {
+            return Err(SignerError::RootMismatch);
+        }
+        if let Some(ref siblings) = self.siblings {
+            let leaf = leaf_public_key::<A, F>(&self.master_seed, &self.public_seed, self.index, f);
+            let path = XmssPath::from_index(self.index, siblings.clone());
+            if &path.advance(leaf, f) != public.root() {
+                return Err(SignerError::RootMismatch);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sign the message with the next unused one time key and advance the index,
+    /// the signer must be persisted before the signature is published
+    pub fn sign_next<F>(
+        &mut self,
+        message: GenericArray<u8, A::MessageSize>,
+        f: &F,
+    ) -> Result<XmssSignature<A, Block<A>>, SignerError>
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        if self.index >= self.capacity() {
+            return Err(SignerError::Exhausted {
+                capacity: self.capacity(),
+            });
+        }
+        let (index, path) = self.advance(f);
+        Ok(XmssSignature::new(
+            index,
+            Signature::sign(&self.secret_key(index), message),
+            path,
+        ))
+    }
+
+    // use the next `count` keys as `sign_next` does, advancing the kept path past them,
+    // returns the first of them, nothing is used if they do not all fit
+    pub(crate) fn consume<F>(&mut self, count: u64, f: &F) -> Result<u64, SignerError>
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        let first = self.index;
+        let fits = count
+            .checked_add(first)
+            .is_some_and(|end| end <= self.capacity());
+        if !fits {
+            return Err(SignerError::Exhausted {
+                capacity: self.capacity(),
+            });
+        }
+        for _ in 0..count {
+            self.advance(f);
+        }
+        Ok(first)
+    }
+
+    // use the unused key `index`, returns it with its path, keeps the path of the next one
+    fn advance<F>(&mut self, f: &F) -> (u64, XmssPath<Block<A>>)
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        let index = self.index;
+        let siblings = self.siblings(f);
+        self.index += 1;
+
+        let leaf = leaf_public_key::<A, F>(&self.master_seed, &self.public_seed, index, f);
+        let path = XmssPath::from_index(index, siblings.clone());
+        if self.index < self.capacity() {
+            self.siblings = Some(self.next_siblings(path.clone(), leaf, siblings, f));
+        }
+        (index, path)
+    }
+
+    // the secret key of the leaf `index`, bound to it by the public seed
+    pub(crate) fn secret_key(&self, index: u64) -> SecretKey<A> {
+        leaf_secret_key::<A>(&self.master_seed, &self.public_seed, index)
+    }
+
+    /// The path of the next unused key, the one `sign_next` is about to use,
+    /// the first call computes it by treehash, then it is kept and advanced by `sign_next`,
+    /// `None` if all keys are used
+    pub fn current_path<F>(&mut self, f: &F) -> Option<XmssPath<Block<A>>>
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        if self.index >= self.capacity() {
+            return None;
+        }
+        let siblings = self.siblings(f);
+        let path = XmssPath::from_index(self.index, siblings.clone());
+        self.siblings = Some(siblings);
+        Some(path)
+    }
+
+    // take the siblings of the path of `index`, if they are not kept, compute each
+    // by treehash, so only the stack of one subtree is in memory
+    fn siblings<F>(&mut self, f: &F) -> Vec<Block<A>>
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        match self.siblings.take() {
+            Some(siblings) => siblings,
+            None => {
+                let (master_seed, public_seed) = (&self.master_seed, &self.public_seed);
+                let leaf_fn = |index| leaf_public_key::<A, F>(master_seed, public_seed, index, f);
+                (0..self.height)
+                    .map(|level| xmss::treehash(&leaf_fn, level, (self.index >> level) ^ 1, f))
+                    .collect()
+            },
+        }
+    }
+
+    // the path of `index` from the path of `index - 1`, the siblings change up to
+    // the level `tau` of the lowest set bit of `index`, the sibling on it is the left node
+    // containing the previous leaf, below it are the right subtrees computed by treehash
+    fn next_siblings<F>(
+        &self,
+        path: XmssPath<Block<A>>,
+        leaf: Block<A>,
+        mut siblings: Vec<Block<A>>,
+        f: &F,
+    ) -> Vec<Block<A>>
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        let tau = self.index.trailing_zeros() as usize;
+        siblings[tau] = path.advance_to(leaf, tau, f);
+
+        let (master_seed, public_seed) = (&self.master_seed, &self.public_seed);
+        let leaf_fn = |index| leaf_public_key::<A, F>(master_seed, public_seed, index, f);
+        for (level, sibling) in siblings.iter_mut().enumerate().take(tau) {
+            *sibling = xmss::treehash(&leaf_fn, level, (self.index >> level) ^ 1, f);
+        }
+        siblings
+    }
+
+    /// Sign each message with the next unused one time key, the iterator yields
+    /// the error and stops when all keys are used
+    pub fn sign_iter<'a, I, F>(
+        &'a mut self,
+        messages: I,
+        f: &'a F,
+    ) -> impl Iterator<Item = Result<XmssSignature<A, Block<A>>, SignerError>> + 'a
+    where
+        I: IntoIterator<Item = GenericArray<u8, A::MessageSize>>,
+        I::IntoIter: 'a,
+        F: XmssOperation<Block<A>>,
+    {
+        let mut exhausted = false;
+        messages
+            .into_iter()
+            .map_while(move |message| match exhausted {
+                true => None,
+                false => {
+                    let signature = self.sign_next(message, f);
+                    exhausted = signature.is_err();
+                    Some(signature)
+                },
+            })
+    }
+
+    /// Sign the messages with the consecutive unused one time keys, a convenience loop
+    /// of `sign_next` that checks first that all of them fit, otherwise nothing is signed
+    /// and the index stays, the cost is the one of `sign_next` per message,
+    /// persist before publish
+    pub fn sign_batch<F>(
+        &mut self,
+        messages: &[GenericArray<u8, A::MessageSize>],
+        f: &F,
+    ) -> Result<Vec<XmssSignature<A, Block<A>>>, SignerError>
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        if messages.is_empty() {
+            return Ok(Vec::new());
+        }
+        let fits = (messages.len() as u64)
+            .checked_add(self.index)
+            .is_some_and(|end| end <= self.capacity());
+        if !fits {
+            return Err(SignerError::Exhausted {
+                capacity: self.capacity(),
+            });
+        }
+
+        messages
+            .iter()
+            .map(|message| self.sign_next(message.clone(), f))
+            .collect()
+    }
+
+    fn size() -> usize {
+        A::BlockLength::USIZE + 1 + 8
+    }
+
+    /// The master seed, the height as a byte and the index as big endian `u64`,
+    /// followed by the `height` siblings of the path of the next key, if it is computed
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::size());
+        bytes.extend_from_slice(self.master_seed.as_ref());
+        bytes.push(self.height as u8);
+        bytes.extend_from_slice(&[0; 8]);
+        BigEndian::write_u64(&mut bytes[(A::BlockLength::USIZE + 1)..], self.index);
+        if let Some(ref siblings) = self.siblings {
+            siblings
+                .iter()
+                .for_each(|sibling| bytes.extend_from_slice(sibling.as_ref()));
+        }
+        bytes
+    }
+
+    /// Restore the signer, if `last_known_index` is given, the stored index
+    /// should not be lower, otherwise the state is stale and signing would reuse keys
+    pub fn from_bytes(bytes: &[u8], last_known_index: Option<u64>) -> Result<Self, SignerError> {
+        let n = A::BlockLength::USIZE;
+        if bytes.len() < Self::size() {
+            return Err(SignerError::Decode(DecodeError::WrongLength {
+                expected: Self::size(),
+                got: bytes.len(),
+            }));
+        }
+
+        let height = bytes[n] as usize;
+        seed::check_height(height)?;
+        let index = BigEndian::read_u64(&bytes[(n + 1)..]);
+        if index > seed::capacity(height) {
+            return Err(SignerError::BadIndex {
+                index,
+                capacity: seed::capacity(height),
+            });
+        }
+        match last_known_index {
+            Some(last_known_index) if index < last_known_index => {
+                return Err(SignerError::IndexRollback {
+                    index,
+                    last_known_index,
+                })
+            },
+            _ => (),
+        }
+
+        // the exhausted signer has no next path
+        let expected = match index < seed::capacity(height) {
+            true => Self::size() + height * n,
+            false => Self::size(),
+        };
+        let siblings = match bytes.len() {
+            length if length == Self::size() => None,
+            length if length == expected => Some(
+                bytes[Self::size()..]
+                    .chunks(n)
+                    .map(GenericArray::clone_from_slice)
+                    .collect(),
+            ),
+            length => {
+                return Err(SignerError::Decode(DecodeError::WrongLength {
+                    expected,
+                    got: length,
+                }))
+            },
+        };
+
+        let master_seed = GenericArray::clone_from_slice(&bytes[..n]);
+        Ok(Signer {
+            public_seed: derive::<A>(&master_seed, Domain::PublicSeed, 0),
+            master_seed,
+            height,
+            index,
+            siblings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use digest::{
+        generic_array::typenum::{U32, U15},
+        Digest,
+    };
+    use super::super::{sha256::Sha256, HashOperation, SignerError};
+    use super::Signer;
+
+    type A = (U32, U32, U15, Sha256, ());
+
+    fn node() -> HashOperation<Sha256> {
+        HashOperation::new(b"node")
+    }
+
+    #[test]
+    fn test_signer() {
+        let mut signer = Signer::<A>::new(Sha256::digest(b"master seed"), 1).unwrap();
+        let root = signer.root(&node());
+        let message = Sha256::digest(b"message");
+
+        let signature = signer.sign_next(message, &node()).unwrap();
+        assert_eq!(signature.index(), 0);
+        assert!(signature.verify(signer.public_seed(), &root, message, &node()));
+
+        let stored = signer.to_bytes();
+        let mut signer = Signer::<A>::from_bytes(&stored, Some(1)).unwrap();
+        let signature = signer.sign_next(message, &node()).unwrap();
+        assert_eq!(signature.index(), 1);
+        assert!(signature.verify(signer.public_seed(), &root, message, &node()));
+
+        assert_eq!(
+            signer.sign_next(message, &node()).err(),
+            Some(SignerError::Exhausted { capacity: 2 }),
+        );
+        assert_eq!(
+            Signer::<A>::from_bytes(&stored, Some(2)).err(),
+            Some(SignerError::IndexRollback {
+                index: 1,
+                last_known_index: 2,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_signer_tall() {
+        let master_seed = Sha256::digest(b"master seed");
+        for &(height, capacity) in &[
+            (31, 1u64 << 31),
+            (32, 1 << 32),
+            (33, 1 << 33),
+            (63, 1 << 63),
+        ] {
+            let signer = Signer::<A>::new(master_seed, height).unwrap();
+            assert_eq!(signer.capacity(), capacity);
+
+            let mut bytes = signer.to_bytes();
+            bytes[33..].copy_from_slice(&capacity.to_be_bytes());
+            assert_eq!(
+                Signer::<A>::from_bytes(&bytes, None).unwrap().index(),
+                capacity
+            );
+            bytes[33..].copy_from_slice(&(capacity + 1).to_be_bytes());
+            assert!(Signer::<A>::from_bytes(&bytes, None).is_err());
+        }
+        assert_eq!(
+            Signer::<A>::new(master_seed, 64).err(),
+            Some(SignerError::BadHeight { height: 64 }),
+        );
+        let mut bytes = Signer::<A>::new(master_seed, 1).unwrap().to_bytes();
+        bytes[32] = 64;
+        assert_eq!(
+            Signer::<A>::from_bytes(&bytes, None).err(),
+            Some(SignerError::BadHeight { height: 64 }),
+        );
+    }
+
+    #[test]
+    fn test_signer_traversal() {
+        let master_seed = Sha256::digest(b"master seed");
+        let message = Sha256::digest(b"message");
+        let mut signer = Signer::<A>::new(master_seed, 3).unwrap();
+        let tree = signer.tree(&node()).cache(&node());
+
+        let mut uninterrupted = signer.clone();
+        let expected = (0..8)
+            .map(|_| uninterrupted.sign_next(message, &node()).unwrap())
+            .collect::<Vec<_>>();
+        for (index, signature) in expected.iter().enumerate() {
+            assert_eq!(signature.path(), &tree.path(index as u64));
+        }
+
+        for _ in 0..3 {
+            signer.sign_next(message, &node()).unwrap();
+        }
+        let stored = signer.to_bytes();
+        assert_eq!(stored.len(), 32 + 1 + 8 + 3 * 32);
+        let mut signer = Signer::<A>::from_bytes(&stored, Some(3)).unwrap();
+        for signature in &expected[3..] {
+            let resumed = signer.sign_next(message, &node()).unwrap();
+            assert_eq!(resumed.index(), signature.index());
+            assert_eq!(resumed.path(), signature.path());
+        }
+        assert_eq!(signer.to_bytes().len(), 32 + 1 + 8);
+
+        assert!(Signer::<A>::from_bytes(&stored[..stored.len() - 1], None).is_err());
+        assert_eq!(
+            Signer::<A>::from_bytes(&stored, Some(4)).err(),
+            Some(SignerError::IndexRollback {
+                index: 3,
+                last_known_index: 4,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_signer_current_path() {
+        let mut signer = Signer::<A>::new(Sha256::digest(b"master seed"), 2).unwrap();
+        let root = signer.root(&node());
+        let message = Sha256::digest(b"message");
+        for index in 0..4 {
+            let path = signer.current_path(&node()).unwrap();
+            assert_eq!(path.leaf_index(), index);
+            assert_eq!(signer.current_path(&node()), Some(path.clone()));
+            let signature = signer.sign_next(message, &node()).unwrap();
+            assert_eq!(signature.path(), &path);
+            assert!(signature.verify(signer.public_seed(), &root, message, &node()));
+        }
+        assert!(signer.current_path(&node()).is_none());
+    }
+
+    #[test]
+    fn test_signer_self_check() {
+        use super::super::XmssKeyPair;
+
+        let (mut signer, public) =
+            XmssKeyPair::generate::<A, _>(Sha256::digest(b"master seed"), 2, &node()).unwrap();
+        assert_eq!(signer.self_check(&public, &node()), Ok(()));
+        signer
+            .sign_next(Sha256::digest(b"message"), &node())
+            .unwrap();
+        assert_eq!(signer.self_check(&public, &node()), Ok(()));
+
+        let stored = signer.to_bytes();
+        let reloaded = Signer::<A>::from_bytes(&stored, Some(1)).unwrap();
+        assert_eq!(reloaded.self_check(&public, &node()), Ok(()));
+
+        for &position in &[0, stored.len() - 1] {
+            let mut corrupted = stored.clone();
+            corrupted[position] ^= 1;
+            let corrupted = Signer::<A>::from_bytes(&corrupted, Some(1)).unwrap();
+            assert_eq!(
+                corrupted.self_check(&public, &node()),
+                Err(SignerError::RootMismatch),
+            );
+        }
+
+        let (_, other) =
+            XmssKeyPair::generate::<A, _>(Sha256::digest(b"other seed"), 2, &node()).unwrap();
+        assert_eq!(
+            signer.self_check(&other, &node()),
+            Err(SignerError::RootMismatch),
+        );
+    }
+
+    #[test]
+    fn test_signer_sign_batch() {
+        let mut signer = Signer::<A>::new(Sha256::digest(b"master seed"), 2).unwrap();
+        let root = signer.root(&node());
+        let messages = [b"a", b"b", b"c"]
+            .iter()
+            .map(|m| Sha256::digest(m.as_ref()))
+            .collect::<Vec<_>>();
+
+        // nothing is computed for the empty batch
+        assert!(signer.sign_batch(&[], &node()).unwrap().is_empty());
+        assert_eq!(signer.to_bytes().len(), 32 + 1 + 8);
+
+        let signatures = signer.sign_batch(&messages[..1], &node()).unwrap();
+        assert_eq!(signer.index(), 1);
+        assert!(signatures[0].verify(signer.public_seed(), &root, messages[0], &node()));
+
+        assert_eq!(
+            signer
+                .sign_batch(&[messages.clone(), messages.clone()].concat(), &node())
+                .err(),
+            Some(SignerError::Exhausted { capacity: 4 }),
+        );
+        assert_eq!(signer.index(), 1);
+
+        let mut one_by_one = signer.clone();
+        let signatures = signer.sign_batch(&messages, &node()).unwrap();
+        assert_eq!(signer.index(), 4);
+        for (i, signature) in signatures.iter().enumerate() {
+            assert_eq!(signature.index(), i as u64 + 1);
+            assert!(signature.verify(signer.public_seed(), &root, messages[i], &node()));
+            let expected = one_by_one.sign_next(messages[i], &node()).unwrap();
+            assert_eq!(signature.to_bytes(), expected.to_bytes());
+        }
+        assert!(signer.sign_batch(&[], &node()).unwrap().is_empty());
+        assert!(signer.sign_batch(&messages[..1], &node()).is_err());
+    }
+
+    #[test]
+    fn test_signer_sign_iter() {
+        let mut signer = Signer::<A>::new(Sha256::digest(b"master seed"), 1).unwrap();
+        let root = signer.root(&node());
+        let messages = [b"a", b"b", b"c", b"d"]
+            .iter()
+            .map(|m| Sha256::digest(m.as_ref()))
+            .collect::<Vec<_>>();
+
+        let signatures = signer
+            .sign_iter(messages.clone(), &node())
+            .collect::<Vec<_>>();
+        assert_eq!(signatures.len(), 3);
+        for (index, signature) in signatures[..2].iter().enumerate() {
+            let signature = signature.as_ref().ok().unwrap();
+            assert_eq!(signature.index(), index as u64);
+            assert!(signature.verify(signer.public_seed(), &root, messages[index], &node()));
+        }
+        assert_eq!(
+            signatures[2].as_ref().err(),
+            Some(&SignerError::Exhausted { capacity: 2 }),
+        );
+    }
+}
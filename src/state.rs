@@ -4,15 +4,84 @@ use core::{
     marker::PhantomData,
 };
 use digest::{
-    generic_array::{GenericArray, ArrayLength, typenum::Unsigned},
+    generic_array::{GenericArray, ArrayLength, typenum::Unsigned, sequence::GenericSequence},
     Digest,
 };
+use super::error::{DecodeError, ParamError, MessageError};
+use super::params::{HashId, ChecksumPlacement, ParamHeader};
+use super::seed::{expand, expand_keyed, mask};
+use super::prf::Prf;
 
 pub trait WOtsPlus {
     type BlockLength: ArrayLength<u8>;
     type MessageSize: ArrayLength<u8>;
     type WinternitzMinusOne: ArrayLength<GenericArray<u8, Self::BlockLength>>;
     type Digest: Digest<OutputSize = Self::BlockLength>;
+
+    /// The identity of the digest written in the tagged serialization,
+    /// the parameters tuple does not know it
+    const HASH_ID: HashId = HashId::Unspecified;
+
+    /// The order of the chains, both the signer and the verifier follow it
+    const CHECKSUM_PLACEMENT: ChecksumPlacement = ChecksumPlacement::Append;
+
+    /// The keyed function of the seed derivation, `H(key || input)` unless overridden,
+    /// it is not HMAC, so SHA-2 needs the sixth element of the parameters tuple, see `Prf`
+    fn prf(key: &[u8], input: &[u8]) -> GenericArray<u8, Self::BlockLength> {
+        <() as Prf<Self::Digest>>::prf(key, input)
+    }
+
+    /// Check the parameters are meaningful and supported by the implementation,
+    /// the constructor of the state panics with this error otherwise
+    fn validate_params() -> Result<(), ParamError>
+    where
+        Self: Sized,
+    {
+        let n = Self::BlockLength::USIZE;
+        let m = Self::MessageSize::USIZE;
+        let w = Self::WinternitzMinusOne::USIZE;
+
+        if n == 0 {
+            return Err(ParamError::ZeroBlockLength);
+        }
+        if m == 0 {
+            return Err(ParamError::ZeroMessageSize);
+        }
+        validate_winternitz(w)
+    }
+}
+
+pub(crate) fn validate_winternitz(w: usize) -> Result<(), ParamError> {
+    if w == 0 || (w & (w + 1)) != 0 {
+        return Err(ParamError::WinternitzNotPowerOfTwoMinusOne {
+            winternitz_minus_one: w,
+        });
+    }
+    // the digit is a byte, the single bit digit makes no sense
+    let digit_bits = (w + 1).trailing_zeros() as usize;
+    if !(2..=8).contains(&digit_bits) {
+        return Err(ParamError::UnsupportedWinternitz {
+            winternitz_minus_one: w,
+        });
+    }
+    Ok(())
+}
+
+// `State::lengths` of the message size and `WinternitzMinusOne` known at runtime,
+// `l2` is the smallest count with `w ^ l2 > l1 * w`, a bound on `(w + 1) ^ l2`,
+// so the checksum always fits, for the valid `w >= 3` the loop runs at least once,
+// since `w <= l1 * w`, therefore `l2 >= 2` and the checksum slice is never empty
+pub(crate) const fn lengths_for(m: usize, w: usize) -> (usize, usize) {
+    let bits = (usize::BITS - 1 - (w + 1).leading_zeros()) as usize;
+    let l1 = (m * 8).div_ceil(bits);
+
+    let mut l2 = 1;
+    let mut power = w;
+    while w > 1 && power <= l1 * w {
+        power *= w;
+        l2 += 1;
+    }
+    (l1, l2)
 }
 
 impl<N, M, W, D, R> WOtsPlus for (N, M, W, D, R)
@@ -28,36 +97,112 @@ where
     type Digest = D;
 }
 
-#[derive(Clone, Eq, PartialEq)]
+/// Same as the five elements tuple, the sixth element is the `Prf` of the seed derivation
+impl<N, M, W, D, R, P> WOtsPlus for (N, M, W, D, R, P)
+where
+    N: ArrayLength<u8>,
+    M: ArrayLength<u8>,
+    W: ArrayLength<GenericArray<u8, N>>,
+    D: Digest<OutputSize = N>,
+    P: Prf<D>,
+{
+    type BlockLength = N;
+    type MessageSize = M;
+    type WinternitzMinusOne = W;
+    type Digest = D;
+
+    fn prf(key: &[u8], input: &[u8]) -> GenericArray<u8, N> {
+        P::prf(key, input)
+    }
+}
+
 pub struct State<A>
 where
     A: WOtsPlus,
 {
     randomization: GenericArray<GenericArray<u8, A::BlockLength>, A::WinternitzMinusOne>,
     data: Vec<GenericArray<u8, A::BlockLength>>,
+    address: Option<(GenericArray<u8, A::BlockLength>, u64)>,
+    randomization_seed: Option<GenericArray<u8, A::BlockLength>>,
+}
+
+// the same for `Clone`, the derive would require `A: Clone`
+impl<A> Clone for State<A>
+where
+    A: WOtsPlus,
+{
+    fn clone(&self) -> Self {
+        State {
+            randomization: self.randomization.clone(),
+            data: self.data.clone(),
+            address: self.address.clone(),
+            randomization_seed: self.randomization_seed.clone(),
+        }
+    }
+}
+
+// the parameters tuple usually contains a digest which is not comparable,
+// so do not require `A: PartialEq` as derive would do, the seed of the randomization
+// is not compared, it is how the randomization is stored, not a part of the value
+impl<A> PartialEq for State<A>
+where
+    A: WOtsPlus,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.randomization == other.randomization
+            && self.data == other.data
+            && self.address == other.address
+    }
 }
 
+impl<A> Eq for State<A> where A: WOtsPlus {}
+
 impl<A> State<A>
 where
     A: WOtsPlus,
 {
-    pub fn lengths() -> (usize, usize) {
-        let m = A::MessageSize::U64 as f64;
-        let w = A::WinternitzMinusOne::U64 as f64;
-        let l1 = (m * 8.0 / (w + 1.0).log2()).ceil();
-        let l2 = 1.0 + ((l1 * w).log2() / w.log2()).floor();
-        (l1 as usize, l2 as usize)
+    /// The bound `Digest<OutputSize = Self::BlockLength>` already guarantees it,
+    /// evaluated when the state is constructed, so a manual `WOtsPlus` impl
+    /// which somehow breaks it fails to compile instead of misbehaving
+    const BLOCK_LENGTH_IS_DIGEST_SIZE: () = assert!(
+        <A::Digest as Digest>::OutputSize::USIZE == A::BlockLength::USIZE,
+        "`BlockLength` should be equal to the output size of the `Digest`",
+    );
+
+    /// `l1 = ceil(8 * m / log2(w + 1))` and `l2 = 1 + floor(log_w(l1 * w))`,
+    /// where `w` is `WinternitzMinusOne`, computed in integers
+    pub const fn lengths() -> (usize, usize) {
+        lengths_for(A::MessageSize::USIZE, A::WinternitzMinusOne::USIZE)
     }
 
     pub fn new(
         randomization: GenericArray<GenericArray<u8, A::BlockLength>, A::WinternitzMinusOne>,
         data: Vec<GenericArray<u8, A::BlockLength>>,
     ) -> Self {
+        let () = Self::BLOCK_LENGTH_IS_DIGEST_SIZE;
+        if let Err(error) = A::validate_params() {
+            panic!("{}", error)
+        }
         let (l1, l2) = Self::lengths();
         assert_eq!(l1 + l2, data.len());
         State {
-            randomization: randomization,
-            data: data,
+            randomization,
+            data,
+            address: None,
+            randomization_seed: None,
+        }
+    }
+
+    /// Bind the chains to the position of the one time key, the mask of the step `j`
+    /// of the chain `i` becomes `r_j ^ H(public_seed || 1 || leaf || i || j)`,
+    /// so the same chain values in different leaves give unrelated tips,
+    /// the address is not serialized, the verifier should set the same one
+    pub fn with_address(self, public_seed: GenericArray<u8, A::BlockLength>, leaf: u64) -> Self {
+        State {
+            randomization: self.randomization,
+            data: self.data,
+            address: Some((public_seed, leaf)),
+            randomization_seed: self.randomization_seed,
         }
     }
 
@@ -67,13 +212,343 @@ where
         &self.randomization
     }
 
+    // move the value out and leave the empty state, for the owner that wipes on drop
+    pub(crate) fn take(&mut self) -> Self {
+        State {
+            randomization: core::mem::take(&mut self.randomization),
+            data: core::mem::take(&mut self.data),
+            address: self.address.take(),
+            randomization_seed: self.randomization_seed.take(),
+        }
+    }
+
+    // the chains of the secret key are wiped on drop, see `SecretKey`
+    #[cfg(feature = "zeroize")]
+    pub(crate) fn wipe(&mut self) {
+        super::wipe::wipe(&mut self.data);
+    }
+
     pub fn data(&self) -> &[GenericArray<u8, A::BlockLength>] {
         self.data.as_ref()
     }
 
+    /// Whether the randomization has an all zero row or two equal rows, a cheap check
+    /// of the imported keys against a broken random generator or corrupted data
+    pub fn randomization_is_weak(&self) -> bool {
+        use std::collections::HashSet;
+
+        let mut rows = HashSet::with_capacity(self.randomization.len());
+        self.randomization
+            .iter()
+            .any(|row| row.iter().all(|&b| b == 0) || !rows.insert(row))
+    }
+
+    /// The chains as byte rows without copying, the crate forbids unsafe code,
+    /// so the rows are not reinterpreted as a single slice
+    pub fn data_rows(&self) -> impl ExactSizeIterator<Item = &[u8]> {
+        self.data.iter().map(|block| block.as_slice())
+    }
+
+    /// The randomization as byte rows without copying, see `data_rows`
+    pub fn randomization_rows(&self) -> impl ExactSizeIterator<Item = &[u8]> {
+        self.randomization.iter().map(|block| block.as_slice())
+    }
+
     pub fn project(self) -> Vec<GenericArray<u8, A::BlockLength>> {
         self.data
     }
+
+    /// Replace the randomization and keep the chains, the tips depend on
+    /// the randomization, so for a secret key it is a new key pair,
+    /// the previous public key does not verify its signatures
+    pub fn with_randomization(
+        self,
+        randomization: GenericArray<GenericArray<u8, A::BlockLength>, A::WinternitzMinusOne>,
+    ) -> Self {
+        State {
+            randomization,
+            data: self.data,
+            address: self.address,
+            randomization_seed: None,
+        }
+    }
+
+    /// Replace the randomization by the one expanded from the epoch seed,
+    /// see `with_randomization`, the same seed gives the same key pair again
+    pub fn rerandomize_from_seed(self, epoch_seed: &[u8]) -> Self {
+        let n = A::BlockLength::USIZE;
+        let bytes = expand_keyed::<A>(epoch_seed, A::WinternitzMinusOne::USIZE * n);
+        let mut blocks = bytes.chunks(n).map(GenericArray::clone_from_slice);
+        let randomization = GenericArray::generate(|_| blocks.next().unwrap());
+        self.with_randomization(randomization)
+    }
+
+    /// Same as `rerandomize_from_seed`, but the seed is a block and it is kept,
+    /// so `to_bytes_compact` stores the seed instead of the randomization
+    pub fn with_randomization_seed(self, seed: GenericArray<u8, A::BlockLength>) -> Self {
+        let mut s = self.rerandomize_from_seed(&seed);
+        s.randomization_seed = Some(seed);
+        s
+    }
+
+    /// The seed of the randomization, if it is set by `with_randomization_seed`
+    pub fn randomization_seed(&self) -> Option<&GenericArray<u8, A::BlockLength>> {
+        self.randomization_seed.as_ref()
+    }
+
+    fn chain(
+        &self,
+        block: &GenericArray<u8, A::BlockLength>,
+        index: usize,
+        range: Range<usize>,
+    ) -> GenericArray<u8, A::BlockLength> {
+        let start = range.start;
+        match self.address {
+            None => chain_advance::<A>(block, &self.randomization, start, range.len()),
+            Some((ref public_seed, leaf)) => {
+                self.randomization[range]
+                    .iter()
+                    .enumerate()
+                    .fold(block.clone(), |b, (j, a)| {
+                        let m = mask::<A>(public_seed, leaf, index, start + j);
+                        let v =
+                            GenericArray::<u8, A::BlockLength>::generate(|i| a[i] ^ m[i] ^ b[i]);
+                        A::Digest::new().chain(v).result()
+                    })
+            },
+        }
+    }
+
+    /// Same as `self * message`, but the message should have a digit for each chain,
+    /// where the multiplication panics
+    pub fn try_mul(&self, message: &Message<A>) -> Result<Self, MessageError> {
+        if self.data.len() != message.ranges.len() {
+            return Err(MessageError::ChainCountMismatch {
+                data: self.data.len(),
+                ranges: message.ranges.len(),
+            });
+        }
+        Ok(State {
+            randomization: self.randomization.clone(),
+            data: self
+                .data
+                .iter()
+                .zip(message.ranges.iter().cloned())
+                .enumerate()
+                .map(|(index, (block, range))| self.chain(block, index, range))
+                .collect(),
+            address: self.address.clone(),
+            randomization_seed: self.randomization_seed.clone(),
+        })
+    }
+
+    /// Same as `self * message`, but the chains are advanced in the given order,
+    /// the order is a permutation of the chain indices, it affects only the performance
+    pub fn advance_ordered(&self, message: Message<A>, order: &[usize]) -> Self {
+        let mut visited = vec![false; self.data.len()];
+        order.iter().for_each(|&i| {
+            assert!(!visited[i], "the order should be a permutation");
+            visited[i] = true;
+        });
+        assert!(
+            visited.iter().all(|&v| v),
+            "the order should be a permutation"
+        );
+        assert_eq!(message.ranges.len(), self.data.len());
+
+        let mut data = self.data.clone();
+        order
+            .iter()
+            .for_each(|&i| data[i] = self.chain(&self.data[i], i, message.ranges[i].clone()));
+        State {
+            randomization: self.randomization.clone(),
+            data,
+            address: self.address.clone(),
+            randomization_seed: self.randomization_seed.clone(),
+        }
+    }
+
+    /// Advance each chain by the message and compare with `expected` on the fly,
+    /// the comparison of the blocks does not depend on where they differ
+    pub fn tips_match(
+        &self,
+        message: &Message<A>,
+        expected: &[GenericArray<u8, A::BlockLength>],
+    ) -> bool {
+        self.tips_match_in(message, expected, 0..expected.len())
+    }
+
+    // same as `tips_match`, but only the chains in the range are advanced and compared
+    pub(crate) fn tips_match_in(
+        &self,
+        message: &Message<A>,
+        expected: &[GenericArray<u8, A::BlockLength>],
+        chains: Range<usize>,
+    ) -> bool {
+        if self.data.len() != expected.len()
+            || message.ranges.len() != expected.len()
+            || chains.end > expected.len()
+        {
+            return false;
+        }
+
+        let difference = self
+            .data
+            .iter()
+            .zip(message.ranges.iter())
+            .zip(expected.iter())
+            .enumerate()
+            .skip(chains.start)
+            .take(chains.end - chains.start)
+            .fold(0, |difference, (index, ((block, range), expected))| {
+                self.chain(block, index, range.clone())
+                    .iter()
+                    .zip(expected.iter())
+                    .fold(difference, |difference, (a, b)| difference | (a ^ b))
+            });
+        difference == 0
+    }
+
+    // same as `tips_match`, but stops at the first chain that differs,
+    // so the time reveals where the signature is wrong
+    pub(crate) fn tips_match_fast(
+        &self,
+        message: &Message<A>,
+        expected: &[GenericArray<u8, A::BlockLength>],
+    ) -> bool {
+        self.data.len() == expected.len()
+            && message.ranges.len() == expected.len()
+            && self
+                .data
+                .iter()
+                .zip(message.ranges.iter())
+                .zip(expected.iter())
+                .enumerate()
+                .all(|(index, ((block, range), expected))| {
+                    self.chain(block, index, range.clone()) == *expected
+                })
+    }
+
+    // the number of digest invocations of `self * message`, a chain step
+    // takes one hash, and one more for the mask if the chains are addressed
+    pub(crate) fn hash_calls(&self, message: &Message<A>) -> usize {
+        let per_step = if self.address.is_some() { 2 } else { 1 };
+        message
+            .ranges
+            .iter()
+            .take(self.data.len())
+            .map(|range| range.len() * per_step)
+            .sum()
+    }
+
+    pub const fn size() -> usize {
+        let (l1, l2) = Self::lengths();
+        (A::WinternitzMinusOne::USIZE + l1 + l2) * A::BlockLength::USIZE
+    }
+
+    /// The randomization rows concatenated, the same bytes `to_bytes` starts with
+    pub fn randomization_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::size());
+        self.randomization
+            .iter()
+            .for_each(|block| bytes.extend_from_slice(block.as_ref()));
+        bytes
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.randomization_bytes();
+        self.data
+            .iter()
+            .for_each(|block| bytes.extend_from_slice(block.as_ref()));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let () = Self::BLOCK_LENGTH_IS_DIGEST_SIZE;
+        if bytes.len() != Self::size() {
+            return Err(DecodeError::WrongLength {
+                expected: Self::size(),
+                got: bytes.len(),
+            });
+        }
+
+        let mut blocks = bytes
+            .chunks(A::BlockLength::USIZE)
+            .map(GenericArray::clone_from_slice);
+        let randomization = GenericArray::generate(|_| blocks.next().unwrap());
+        Ok(State {
+            randomization,
+            data: blocks.collect(),
+            address: None,
+            randomization_seed: None,
+        })
+    }
+
+    /// The flag `1` followed by the seed of the randomization and the chains,
+    /// or the flag `0` followed by `to_bytes` if the randomization has no seed
+    pub fn to_bytes_compact(&self) -> Vec<u8> {
+        match &self.randomization_seed {
+            Some(seed) => {
+                let mut bytes = vec![1];
+                bytes.extend_from_slice(seed.as_ref());
+                self.data
+                    .iter()
+                    .for_each(|block| bytes.extend_from_slice(block.as_ref()));
+                bytes
+            },
+            &None => {
+                let mut bytes = vec![0];
+                bytes.extend_from_slice(&self.to_bytes());
+                bytes
+            },
+        }
+    }
+
+    /// The randomization is expanded from the seed again, it is the same matrix
+    pub fn from_bytes_compact(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (l1, l2) = Self::lengths();
+        let n = A::BlockLength::USIZE;
+        match bytes.first() {
+            None => Err(DecodeError::WrongLength {
+                expected: 1 + (1 + l1 + l2) * n,
+                got: 0,
+            }),
+            Some(&0) => Self::from_bytes(&bytes[1..]),
+            Some(&1) => {
+                if bytes.len() != 1 + (1 + l1 + l2) * n {
+                    return Err(DecodeError::WrongLength {
+                        expected: 1 + (1 + l1 + l2) * n,
+                        got: bytes.len(),
+                    });
+                }
+                let seed = GenericArray::clone_from_slice(&bytes[1..(1 + n)]);
+                let data = bytes[(1 + n)..]
+                    .chunks(n)
+                    .map(GenericArray::clone_from_slice)
+                    .collect();
+                let randomization = GenericArray::generate(|_| GenericArray::default());
+                Ok(State::new(randomization, data).with_randomization_seed(seed))
+            },
+            Some(&flag) => Err(DecodeError::UnknownRandomizationFlag { flag }),
+        }
+    }
+}
+
+impl<A> State<A>
+where
+    A: WOtsPlus,
+{
+    /// The `ParamHeader` of `A` followed by `to_bytes`
+    pub fn to_bytes_tagged(&self) -> Vec<u8> {
+        let mut bytes = ParamHeader::of::<A>().to_bytes().to_vec();
+        bytes.extend_from_slice(&self.to_bytes());
+        bytes
+    }
+
+    /// Fails with `ParamMismatch` if the bytes are made with other parameters
+    pub fn from_bytes_tagged(bytes: &[u8]) -> Result<Self, DecodeError> {
+        ParamHeader::strip::<A>(bytes).and_then(Self::from_bytes)
+    }
 }
 
 impl<A> fmt::Debug for State<A>
@@ -101,6 +576,10 @@ where
     }
 }
 
+/// The steps of each chain, the digits of the message and of its checksum,
+/// they are not secret unless the message is, e.g. a secret commitment,
+/// only then the `zeroize` feature is needed, it wipes the digits on drop,
+/// the clones too, see `explain_into` for the copy of the digits
 pub struct Message<A>
 where
     A: WOtsPlus,
@@ -109,6 +588,29 @@ where
     phantom_data: PhantomData<A>,
 }
 
+#[cfg(feature = "zeroize")]
+impl<A> Drop for Message<A>
+where
+    A: WOtsPlus,
+{
+    fn drop(&mut self) {
+        super::wipe::wipe(&mut self.ranges);
+        self.ranges.clear();
+    }
+}
+
+impl<A> Clone for Message<A>
+where
+    A: WOtsPlus,
+{
+    fn clone(&self) -> Self {
+        Message {
+            ranges: self.ranges.clone(),
+            phantom_data: PhantomData,
+        }
+    }
+}
+
 impl<A> Message<A>
 where
     A: WOtsPlus,
@@ -125,6 +627,8 @@ where
         }
     }
 
+    /// Advances each chain from the secret to the tip, it is the same for the parameter set,
+    /// so a verifier may keep one and multiply by reference, `&state * &infinity`
     pub fn infinity() -> Self
     where
         A: WOtsPlus,
@@ -132,73 +636,357 @@ where
         let (l1, l2) = State::<A>::lengths();
         Message {
             ranges: (0..(l1 + l2))
-                .map(|_| 0..(A::WinternitzMinusOne::USIZE + 1))
+                .map(|_| 0..A::WinternitzMinusOne::USIZE)
                 .collect(),
             phantom_data: PhantomData,
         }
     }
 
+    /// The steps the verifier applies after the signer, each chain `0..d` becomes `d..w`,
+    /// so the signature advanced by the inverse gives the tips of the public key
     pub fn inverse(self) -> Self {
         Message {
             ranges: self
                 .ranges
-                .into_iter()
-                .map(|Range { start: _, end: e }| e..(A::WinternitzMinusOne::USIZE + 1))
+                .iter()
+                .map(|&Range { start: _, end: e }| e..A::WinternitzMinusOne::USIZE)
                 .collect(),
             phantom_data: PhantomData,
         }
     }
 
+    /// Whether the messages together advance each chain exactly from the secret
+    /// to the tip, e.g. `m.clone().inverse().is_inverse_of(&m)`
+    pub fn is_inverse_of(&self, other: &Self) -> bool {
+        let w = A::WinternitzMinusOne::USIZE;
+        let covers =
+            |a: &Range<usize>, b: &Range<usize>| a.start == 0 && a.end == b.start && b.end == w;
+        self.ranges.len() == other.ranges.len()
+            && self
+                .ranges
+                .iter()
+                .zip(other.ranges.iter())
+                .all(|(a, b)| covers(a, b) || covers(b, a))
+    }
+
+    /// The digits in the chain order, the checksum included, each digit is at most
+    /// `WinternitzMinusOne`, the number of digits is checked by `State::try_mul`
+    pub fn from_digits(digits: &[u8]) -> Result<Self, MessageError> {
+        let w = A::WinternitzMinusOne::USIZE;
+        match digits.iter().position(|&d| (d as usize) > w) {
+            Some(chain) => Err(MessageError::DigitOutOfRange {
+                chain,
+                value: digits[chain] as usize,
+            }),
+            None => Ok(Message {
+                ranges: digits.iter().map(|&d| 0..(d as usize)).collect(),
+                phantom_data: PhantomData,
+            }),
+        }
+    }
+
+    // the indices of the checksum chains, depends on `CHECKSUM_PLACEMENT`
+    pub(crate) fn checksum_chains() -> Range<usize> {
+        let (l1, l2) = State::<A>::lengths();
+        match A::CHECKSUM_PLACEMENT {
+            ChecksumPlacement::Append => l1..(l1 + l2),
+            ChecksumPlacement::Prepend => 0..l2,
+        }
+    }
+
+    pub(crate) fn ranges(&self) -> &[Range<usize>] {
+        self.ranges.as_ref()
+    }
+
+    /// The number of hash iterations applied to each chain, for the signed message
+    /// it is the digit, for `inverse` it is what the verifier applies, `w - 1 - digit`
+    pub fn explain(&self) -> Vec<usize> {
+        self.ranges.iter().map(|r| r.end - r.start).collect()
+    }
+
+    /// Same as `explain`, but into the buffer of the caller, so the copy of the secret
+    /// message is wiped by it, the buffer should be as long as the number of chains
+    pub fn explain_into(&self, counts: &mut [usize]) {
+        assert_eq!(counts.len(), self.ranges.len());
+        counts
+            .iter_mut()
+            .zip(self.ranges.iter())
+            .for_each(|(count, r)| *count = r.end - r.start);
+    }
+
     fn add(self, v: u8) -> Self {
         let mut s = self;
         s.ranges.push(0..(v as usize));
         s
     }
 
-    fn add_many(self, buffer: &[u8], count: usize) -> Self {
-        let Message {
-            ranges: ranges,
-            phantom_data: _,
-        } = match A::WinternitzMinusOne::USIZE {
-            0x0f => buffer
+    fn bits() -> usize {
+        (A::WinternitzMinusOne::USIZE + 1).trailing_zeros() as usize
+    }
+
+    /// Split the buffer in digits of `log2(w + 1)` bits starting from the most significant bit,
+    /// if the bits of the buffer are not a whole number of digits, the final digit
+    /// takes the remaining bits as its low bits, its high bits are zero
+    fn add_many(self, buffer: &[u8]) -> Self {
+        let bits = Self::bits();
+        let mask = A::WinternitzMinusOne::USIZE as u16;
+
+        let (s, accumulator, length) =
+            buffer
                 .iter()
-                .fold(Message::<A>::empty(), |g, &x| g.add(x / 0x10).add(x & 0xf)),
-            0xff => buffer.iter().fold(Message::<A>::empty(), |g, &x| g.add(x)),
-            _ => unimplemented!(),
+                .fold((self, 0u16, 0), |(s, accumulator, length), &x| {
+                    let (mut s, mut accumulator, mut length) =
+                        (s, (accumulator << 8) | (x as u16), length + 8);
+                    while length >= bits {
+                        length -= bits;
+                        s = s.add(((accumulator >> length) & mask) as u8);
+                    }
+                    accumulator &= (1 << length) - 1;
+                    (s, accumulator, length)
+                });
+        match length {
+            0 => s,
+            _ => s.add(accumulator as u8),
+        }
+    }
+
+    // add to the little endian big integer, it grows as needed
+    fn add_big(sum: &mut Vec<u8>, value: u64) {
+        let mut carry = value as u128;
+        let mut i = 0;
+        while carry != 0 {
+            if i == sum.len() {
+                sum.push(0);
+            }
+            carry += sum[i] as u128;
+            sum[i] = carry as u8;
+            carry >>= 8;
+            i += 1;
+        }
+    }
+
+    // the `count` digits of `bits` bits of the little endian big integer,
+    // the most significant first, the bits above the integer are zero
+    fn big_digits(sum: &[u8], bits: usize, count: usize) -> impl Iterator<Item = u8> + '_ {
+        (0..count).rev().map(move |i| {
+            (0..bits).rev().fold(0, |digit, j| {
+                let position = i * bits + j;
+                let bit = sum
+                    .get(position / 8)
+                    .map_or(0, |b| (b >> (position % 8)) & 1);
+                (digit << 1) | bit
+            })
+        })
+    }
+
+    fn checksum(self) -> Self {
+        let (l1, l2) = State::<A>::lengths();
+        let bits = Self::bits();
+        assert!(
+            l2 >= 2,
+            "the checksum has at least two digits, see `validate_params`"
+        );
+
+        // the sum fits in the `l2` digits, so the buffers are not reallocated
+        // and the wipe reaches every copy of the checksum
+        let mut sum = Vec::with_capacity((l2 * bits).div_ceil(8));
+        self.ranges[0..l1]
+            .iter()
+            .for_each(|Range { start: _, end: e }| {
+                Self::add_big(&mut sum, (A::WinternitzMinusOne::USIZE - *e) as u64)
+            });
+        let digits = Self::big_digits(&sum, bits, l2).collect::<Vec<_>>();
+        let s = digits.iter().fold(self, |s, &digit| s.add(digit));
+        #[cfg(feature = "zeroize")]
+        {
+            let mut digits = digits;
+            super::wipe::wipe(&mut sum);
+            super::wipe::wipe(&mut digits);
+        }
+        s
+    }
+
+    /// Sign exactly the given `MessageSize` bytes
+    #[allow(clippy::self_named_constructors)]
+    pub fn message(message: GenericArray<u8, A::MessageSize>) -> Self {
+        Message::empty()
+            .add_many(message.as_ref())
+            .checksum()
+            .place()
+    }
+
+    /// The message of other bytes with the checksum digits of this one, if the new digits
+    /// have the same sum, so the checksum is the same, `None` otherwise or if this is
+    /// not a signer message, e.g. `inverse`. Reusing the message is safe only as usual,
+    /// each one time key signs a single message
+    pub fn reuse_checksum(&self, message: GenericArray<u8, A::MessageSize>) -> Option<Self> {
+        let (l1, l2) = State::<A>::lengths();
+        let (message_at, checksum_at) = match A::CHECKSUM_PLACEMENT {
+            ChecksumPlacement::Append => (0, l1),
+            ChecksumPlacement::Prepend => (l2, 0),
         };
+        if self.ranges.len() != l1 + l2 || self.ranges.iter().any(|r| r.start != 0) {
+            return None;
+        }
+
+        let digits = Message::<A>::empty().add_many(message.as_ref());
+        let sum = |ranges: &[Range<usize>]| ranges.iter().map(|r| r.end).sum::<usize>();
+        if sum(&digits.ranges) != sum(&self.ranges[message_at..(message_at + l1)]) {
+            return None;
+        }
+
+        let checksum = self.ranges[checksum_at..(checksum_at + l2)].iter().cloned();
+        let mut s = digits;
+        s.ranges.extend(checksum);
+        Some(s.place())
+    }
 
-        assert!(ranges.len() >= count);
-        let base = ranges.len() - count;
+    // the digits are built appended, move the checksum to its place
+    fn place(self) -> Self {
+        let (_, l2) = State::<A>::lengths();
         let mut s = self;
-        s.ranges.extend_from_slice(&ranges[base..]);
+        match A::CHECKSUM_PLACEMENT {
+            ChecksumPlacement::Append => (),
+            ChecksumPlacement::Prepend => s.ranges.rotate_right(l2),
+        }
         s
     }
 
-    fn checksum(self) -> Self {
-        use core::mem;
-        use byteorder::{ByteOrder, BigEndian};
+    /// Sign `H(data)` fit to `MessageSize` bytes, rather than the data itself
+    pub fn digest(data: &[u8]) -> Self {
+        Self::message(fit_digest::<A>(A::Digest::digest(data).as_ref()))
+    }
 
-        let (l1, l2) = State::<A>::lengths();
+    /// Sign `H(prefix || data)` fit to `MessageSize` bytes, the prefix should be fixed
+    /// per application context, it is not delimited, so `with_prefix(b"ab", b"c")`
+    /// signs the same as `with_prefix(b"a", b"bc")` and as `digest(b"abc")`
+    pub fn with_prefix(prefix: &[u8], data: &[u8]) -> Self {
+        let full = A::Digest::new().chain(prefix).chain(data).result();
+        Self::message(fit_digest::<A>(full.as_ref()))
+    }
+}
+
+/// Advance the block by `steps` iterations `H(r_j ^ b)` from the chain position `start`,
+/// using the randomization rows `start..(start + steps)`, the chain of `State * Message`
+/// for the chains without an address
+pub fn chain_advance<A>(
+    block: &GenericArray<u8, A::BlockLength>,
+    randomization: &[GenericArray<u8, A::BlockLength>],
+    start: usize,
+    steps: usize,
+) -> GenericArray<u8, A::BlockLength>
+where
+    A: WOtsPlus,
+{
+    assert!(
+        start + steps <= randomization.len(),
+        "the chain should not go past the randomization"
+    );
+    randomization[start..(start + steps)]
+        .iter()
+        .fold(block.clone(), |b, a| {
+            let v = GenericArray::<u8, A::BlockLength>::generate(|i| a[i] ^ b[i]);
+            A::Digest::new().chain(v).result()
+        })
+}
+
+/// Map the digest to `MessageSize` bytes, the first `MessageSize` bytes if it is
+/// not shorter, so the digest of the exact size is kept, otherwise the counter mode
+/// expansion `H(full || 0) || H(full || 1) || ...` truncated, the counter is big endian `u32`
+pub fn fit_digest<A>(full: &[u8]) -> GenericArray<u8, A::MessageSize>
+where
+    A: WOtsPlus,
+{
+    if full.len() >= A::MessageSize::USIZE {
+        GenericArray::clone_from_slice(&full[..A::MessageSize::USIZE])
+    } else {
+        GenericArray::clone_from_slice(&expand::<A::Digest>(full, A::MessageSize::USIZE))
+    }
+}
+
+/// Build the message from the bytes given in pieces, the digits and the checksum sum
+/// are accumulated as the bytes arrive, so `finalize` only appends the checksum digits,
+/// the result is the same as `Message::message` of the concatenation
+pub struct MessageBuilder<A>
+where
+    A: WOtsPlus,
+{
+    message: Message<A>,
+    accumulator: u16,
+    length: usize,
+    bytes: usize,
+    sum: Vec<u8>,
+}
 
-        // works only if `l2` fit in u64, e.g. 3 * `size of group` <= 8
-        assert!(l2 * A::MessageSize::USIZE / l1 <= mem::size_of::<u64>());
+impl<A> Default for MessageBuilder<A>
+where
+    A: WOtsPlus,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A> MessageBuilder<A>
+where
+    A: WOtsPlus,
+{
+    pub fn new() -> Self {
+        MessageBuilder {
+            message: Message::empty(),
+            accumulator: 0,
+            length: 0,
+            bytes: 0,
+            sum: Vec::new(),
+        }
+    }
 
-        let sum = self.ranges[0..l1].iter().fold(
-            0,
-            |sum,
-             &Range {
-                 start: _,
-                 end: ref e,
-             }| { sum + ((A::WinternitzMinusOne::USIZE - e.clone()) as u64) },
+    fn digit(&mut self, digit: u8) {
+        let w = A::WinternitzMinusOne::USIZE;
+        Message::<A>::add_big(&mut self.sum, (w - digit as usize) as u64);
+        self.message.ranges.push(0..(digit as usize));
+    }
+
+    /// Append the bytes, at most `MessageSize` bytes in total
+    pub fn update(&mut self, buffer: &[u8]) {
+        let bits = Message::<A>::bits();
+        let mask = A::WinternitzMinusOne::USIZE as u16;
+
+        self.bytes += buffer.len();
+        assert!(
+            self.bytes <= A::MessageSize::USIZE,
+            "the message is too long"
         );
-        let mut buffer = [0; 8];
-        BigEndian::write_u64(&mut buffer, sum);
-        self.add_many(buffer.as_ref(), l2)
+        for &x in buffer {
+            self.accumulator = (self.accumulator << 8) | (x as u16);
+            self.length += 8;
+            while self.length >= bits {
+                self.length -= bits;
+                let digit = ((self.accumulator >> self.length) & mask) as u8;
+                self.digit(digit);
+            }
+            self.accumulator &= (1 << self.length) - 1;
+        }
     }
 
-    pub fn message(message: GenericArray<u8, A::MessageSize>) -> Self {
-        let (l1, _) = State::<A>::lengths();
-        Message::empty().add_many(message.as_ref(), l1).checksum()
+    /// Exactly `MessageSize` bytes should be given
+    pub fn finalize(self) -> Message<A> {
+        assert_eq!(
+            self.bytes,
+            A::MessageSize::USIZE,
+            "the message is too short"
+        );
+        let mut s = self;
+        if s.length != 0 {
+            let digit = s.accumulator as u8;
+            s.digit(digit);
+        }
+        let (_, l2) = State::<A>::lengths();
+        let digits = Message::<A>::big_digits(&s.sum, Message::<A>::bits(), l2).collect::<Vec<_>>();
+        digits
+            .into_iter()
+            .fold(s.message, |m, digit| m.add(digit))
+            .place()
     }
 }
 
@@ -209,23 +997,515 @@ where
     type Output = State<A>;
 
     fn mul(self, rhs: Message<A>) -> State<A> {
-        use digest::generic_array::sequence::GenericSequence;
+        self * &rhs
+    }
+}
 
-        State {
-            randomization: self.randomization.clone(),
-            data: self
-                .data
+impl<A> Mul<&Message<A>> for &State<A>
+where
+    A: WOtsPlus,
+{
+    type Output = State<A>;
+
+    fn mul(self, rhs: &Message<A>) -> State<A> {
+        self.try_mul(rhs)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::marker::PhantomData;
+    use digest::{
+        generic_array::{
+            GenericArray,
+            typenum::{U0, U1, U7, U14, U15, U16, U32, U48, U255, U511},
+        },
+        Digest,
+    };
+    use super::super::{
+        sha256::Sha256, SecretKey, PublicKey, Signature, ChecksumPlacement, ParamError,
+        MessageError, DecodeError,
+    };
+    use super::{WOtsPlus, State, Message, MessageBuilder, fit_digest, chain_advance, lengths_for};
+
+    type A = (U32, U32, U15, Sha256, ());
+
+    fn secret_key() -> SecretKey<A> {
+        SecretKey::from_seed(&Sha256::digest(b"seed"))
+    }
+
+    #[test]
+    fn test_big_checksum() {
+        let mut sum = Vec::new();
+        Message::<A>::add_big(&mut sum, u64::MAX);
+        Message::<A>::add_big(&mut sum, u64::MAX);
+        Message::<A>::add_big(&mut sum, 3);
+        let expected = 2 * (u64::MAX as u128) + 3;
+        assert!(expected > 1 << 64);
+        assert_eq!(sum.len(), 9);
+
+        let digits = Message::<A>::big_digits(&sum, 4, 20).collect::<Vec<_>>();
+        let reference = (0..20)
+            .rev()
+            .map(|i| ((expected >> (i * 4)) & 15) as u8)
+            .collect::<Vec<_>>();
+        assert_eq!(digits, reference);
+        assert_eq!(digits[..3], [0, 0, 0]);
+
+        let digits = Message::<A>::big_digits(&sum, 3, 30).collect::<Vec<_>>();
+        let reference = (0..30)
+            .rev()
+            .map(|i| ((expected >> (i * 3)) & 7) as u8)
+            .collect::<Vec<_>>();
+        assert_eq!(digits, reference);
+    }
+
+    #[test]
+    fn test_checksum_length() {
+        for &w in &[3usize, 7, 15, 31, 63, 127, 255] {
+            let bits = (w + 1).trailing_zeros() as usize;
+            for m in 1..=128 {
+                let (l1, l2) = lengths_for(m, w);
+                assert!(l2 >= 2);
+                assert!(((l1 * w) as u128) < 1 << (bits * l2));
+            }
+        }
+
+        // the shortest message, a single digit of the whole byte
+        type A = (U32, U1, U255, Sha256, ());
+        assert_eq!(State::<A>::lengths(), (1, 2));
+        for &(byte, checksum) in &[(0u8, [0, 255]), (255, [0, 0]), (1, [0, 254])] {
+            let explained = Message::<A>::message([byte].into()).explain();
+            assert_eq!(explained, [byte as usize, checksum[0], checksum[1]]);
+        }
+    }
+
+    #[test]
+    fn test_chain_advance() {
+        let secret_key = secret_key();
+        let public_key = PublicKey::from_secret(&secret_key);
+        let message = Sha256::digest(b"message");
+        let signature = Signature::sign(&secret_key, message);
+        let randomization = secret_key.randomization();
+        let digits = Message::<A>::message(message).explain();
+        for (chain, &digit) in digits.iter().enumerate() {
+            let secret = &secret_key.data()[chain];
+            let signed = chain_advance::<A>(secret, randomization, 0, digit);
+            assert_eq!(signed, signature.data()[chain]);
+            let tip = chain_advance::<A>(&signed, randomization, digit, 15 - digit);
+            assert_eq!(tip, public_key.tips()[chain]);
+        }
+
+        let block = Sha256::digest(b"block");
+        let split = chain_advance::<A>(&block, randomization, 2, 5);
+        assert_eq!(
+            chain_advance::<A>(&split, randomization, 7, 4),
+            chain_advance::<A>(&block, randomization, 2, 9),
+        );
+        assert_eq!(chain_advance::<A>(&block, randomization, 15, 0), block);
+    }
+
+    #[test]
+    fn test_randomization_is_weak() {
+        let secret_key = secret_key();
+        assert!(!secret_key.randomization_is_weak());
+
+        let data = secret_key.data().to_vec();
+        let zero = SecretKey::<A>::new(GenericArray::default(), data.clone());
+        assert!(zero.randomization_is_weak());
+
+        let mut randomization = *secret_key.randomization();
+        randomization[9] = randomization[4];
+        assert!(SecretKey::<A>::new(randomization, data.clone()).randomization_is_weak());
+
+        let mut randomization = *secret_key.randomization();
+        randomization[14] = GenericArray::default();
+        assert!(SecretKey::<A>::new(randomization, data).randomization_is_weak());
+    }
+
+    #[test]
+    fn test_validate_params() {
+        assert_eq!(<(U32, U32, U15, Sha256, ())>::validate_params(), Ok(()));
+        assert_eq!(<(U32, U32, U255, Sha256, ())>::validate_params(), Ok(()));
+        assert_eq!(
+            <(U32, U0, U15, Sha256, ())>::validate_params(),
+            Err(ParamError::ZeroMessageSize),
+        );
+        assert_eq!(
+            <(U32, U32, U14, Sha256, ())>::validate_params(),
+            Err(ParamError::WinternitzNotPowerOfTwoMinusOne {
+                winternitz_minus_one: 14,
+            }),
+        );
+        assert_eq!(<(U32, U32, U7, Sha256, ())>::validate_params(), Ok(()));
+        assert_eq!(
+            <(U32, U32, U1, Sha256, ())>::validate_params(),
+            Err(ParamError::UnsupportedWinternitz {
+                winternitz_minus_one: 1,
+            }),
+        );
+        assert_eq!(
+            <(U32, U32, U511, Sha256, ())>::validate_params(),
+            Err(ParamError::UnsupportedWinternitz {
+                winternitz_minus_one: 511,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_advance_ordered() {
+        let secret_key = secret_key();
+        let state = State::new(*secret_key.randomization(), secret_key.data().to_vec());
+        let message = Message::<A>::digest(b"message");
+        let expected = &state * message.clone();
+
+        let reversed = (0..67).rev().collect::<Vec<_>>();
+        assert!(state.advance_ordered(message.clone(), &reversed) == expected);
+        let blocked = (0..4)
+            .flat_map(|i| (0..67).filter(move |j| j % 4 == i))
+            .collect::<Vec<_>>();
+        assert!(state.advance_ordered(message, &blocked) == expected);
+    }
+
+    fn digits<A>(message: Message<A>) -> Vec<usize>
+    where
+        A: WOtsPlus,
+    {
+        message.ranges.iter().map(|r| r.end).collect()
+    }
+
+    #[test]
+    fn test_partial_digit() {
+        // 3 bit digits, 256 bits of the message are 85 digits and a single bit
+        type A = (U32, U32, U7, Sha256, ());
+        assert_eq!(State::<A>::lengths(), (86, 4));
+
+        let d = digits(Message::<A>::message(GenericArray::clone_from_slice(
+            &[0xff; 32],
+        )));
+        assert_eq!(d.len(), 90);
+        assert!(d[..85].iter().all(|&d| d == 7));
+        assert_eq!(d[85], 1);
+        // checksum is 6, its digits are 0, 0, 0, 6
+        assert_eq!(d[86..], [0, 0, 0, 6]);
+
+        let mut message = [0; 32];
+        // digits 101 110 011 000
+        message[0] = 0b1011_1001;
+        message[1] = 0b1000_0000;
+        message[31] = 0b0000_0001;
+        let d = digits(Message::<A>::message(GenericArray::clone_from_slice(
+            &message,
+        )));
+        assert_eq!(d[..4], [0b101, 0b110, 0b011, 0]);
+        assert_eq!(d[84..86], [0, 1]);
+
+        // the whole number of digits is the same as splitting nibbles and bytes
+        type B = (U32, U32, U15, Sha256, ());
+        let d = digits(Message::<B>::message(GenericArray::clone_from_slice(
+            &message,
+        )));
+        assert_eq!(d[..4], [0b1011, 0b1001, 0b1000, 0]);
+        assert_eq!(d[62..64], [0, 1]);
+    }
+
+    #[test]
+    fn test_explain() {
+        let bytes = (0..32).map(|i| (i * 37) as u8).collect::<Vec<_>>();
+        let message = Message::<A>::message(GenericArray::clone_from_slice(&bytes));
+        let counts = message.explain();
+        assert_eq!(counts.len(), 67);
+        let nibbles = bytes
+            .iter()
+            .flat_map(|b| vec![(b >> 4) as usize, (b & 15) as usize])
+            .collect::<Vec<_>>();
+        assert_eq!(counts[..64], nibbles[..]);
+        let sum = nibbles.iter().map(|d| 15 - d).sum::<usize>();
+        assert_eq!(counts[64..], [sum >> 8, (sum >> 4) & 15, sum & 15]);
+
+        let mut buffer = [0; 67];
+        message.explain_into(&mut buffer);
+        assert_eq!(buffer[..], counts[..]);
+
+        let verifier = message.clone().inverse().explain();
+        assert!(counts.iter().zip(verifier).all(|(s, v)| s + v == 15));
+        assert_eq!(digits(message), counts);
+        assert!(Message::<A>::infinity().explain().iter().all(|&c| c == 15));
+    }
+
+    #[test]
+    fn test_rows() {
+        let secret_key = secret_key();
+        let state = State::<A>::new(*secret_key.randomization(), secret_key.data().to_vec());
+        assert_eq!(state.data_rows().len(), 67);
+        assert_eq!(state.randomization_rows().len(), 15);
+
+        let bytes = state.to_bytes();
+        let rows = state
+            .randomization_rows()
+            .chain(state.data_rows())
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(rows, bytes);
+        assert!(state
+            .data_rows()
+            .zip(state.data())
+            .all(|(row, block)| row.as_ptr() == block.as_ptr()));
+    }
+
+    #[test]
+    fn test_is_inverse_of() {
+        let message = Message::<A>::digest(b"message");
+        let inverse = message.clone().inverse();
+        assert!(inverse.is_inverse_of(&message));
+        assert!(message.is_inverse_of(&inverse));
+        assert!(!message.is_inverse_of(&message));
+        assert!(!Message::<A>::digest(b"other").is_inverse_of(&inverse));
+        assert!(Message::<A>::infinity().is_inverse_of(&Message::infinity().inverse()));
+
+        let mut short = inverse.clone();
+        short.ranges.pop();
+        assert!(!short.is_inverse_of(&message));
+    }
+
+    #[test]
+    fn test_bytes_compact() {
+        let secret_key = secret_key();
+        let state = State::<A>::new(*secret_key.randomization(), secret_key.data().to_vec());
+        let bytes = state.to_bytes_compact();
+        assert_eq!(bytes.len(), 1 + (15 + 67) * 32);
+        assert!(State::<A>::from_bytes_compact(&bytes).unwrap() == state);
+
+        let seeded = state.with_randomization_seed(Sha256::digest(b"randomization"));
+        let bytes = seeded.to_bytes_compact();
+        assert_eq!(bytes.len(), 1 + (1 + 67) * 32);
+        let decoded = State::<A>::from_bytes_compact(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), seeded.to_bytes());
+        assert_eq!(decoded.randomization_seed(), seeded.randomization_seed());
+
+        // the signature inherits the seed, replacing the randomization drops it
+        let signed = &seeded * Message::digest(b"message");
+        assert_eq!(signed.to_bytes_compact().len(), bytes.len());
+        let replaced = signed.with_randomization(*secret_key.randomization());
+        assert!(replaced.randomization_seed().is_none());
+
+        assert!(State::<A>::from_bytes_compact(&bytes[1..]).is_err());
+        assert!(State::<A>::from_bytes_compact(&bytes[..100]).is_err());
+        let mut flag = bytes;
+        flag[0] = 2;
+        assert_eq!(
+            State::<A>::from_bytes_compact(&flag).err(),
+            Some(DecodeError::UnknownRandomizationFlag { flag: 2 }),
+        );
+    }
+
+    #[test]
+    fn test_from_digits() {
+        let message = Message::<A>::digest(b"message");
+        let bytes = message
+            .explain()
+            .iter()
+            .map(|&d| d as u8)
+            .collect::<Vec<_>>();
+        let decoded = Message::<A>::from_digits(&bytes).unwrap();
+        assert_eq!(digits(decoded), digits(message));
+
+        let mut over = bytes;
+        over[5] = 15;
+        assert!(Message::<A>::from_digits(&over).is_ok());
+        over[5] = 16;
+        over[9] = 255;
+        assert_eq!(
+            Message::<A>::from_digits(&over).err(),
+            Some(MessageError::DigitOutOfRange {
+                chain: 5,
+                value: 16,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_try_mul() {
+        let secret_key = secret_key();
+        let state = State::new(*secret_key.randomization(), secret_key.data().to_vec());
+        let message = Message::<A>::digest(b"message");
+        assert!(state.try_mul(&message).unwrap() == &state * &message);
+
+        let mut short = message.clone();
+        short.ranges.pop();
+        assert_eq!(
+            state.try_mul(&short).err(),
+            Some(MessageError::ChainCountMismatch {
+                data: 67,
+                ranges: 66,
+            }),
+        );
+        let mut long = message;
+        long.ranges.push(0..1);
+        assert_eq!(
+            state.try_mul(&long).err(),
+            Some(MessageError::ChainCountMismatch {
+                data: 67,
+                ranges: 68,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_message_builder() {
+        type B = (U32, U32, U7, Sha256, ());
+
+        let bytes = (0..32).map(|i| (i * 101 + 7) as u8).collect::<Vec<_>>();
+        for &split in &[0, 1, 13, 31, 32] {
+            let mut builder = MessageBuilder::<A>::new();
+            builder.update(&bytes[..split]);
+            builder.update(&bytes[split..]);
+            assert_eq!(
+                digits(builder.finalize()),
+                digits(Message::<A>::message(GenericArray::clone_from_slice(
+                    &bytes
+                ))),
+            );
+
+            let mut builder = MessageBuilder::<B>::default();
+            bytes
+                .chunks(split.max(1))
+                .for_each(|chunk| builder.update(chunk));
+            assert_eq!(
+                digits(builder.finalize()),
+                digits(Message::<B>::message(GenericArray::clone_from_slice(
+                    &bytes
+                ))),
+            );
+        }
+    }
+
+    #[test]
+    fn test_checksum_placement() {
+        struct Prepend;
+
+        impl WOtsPlus for Prepend {
+            type BlockLength = U32;
+            type MessageSize = U32;
+            type WinternitzMinusOne = U15;
+            type Digest = Sha256;
+
+            const CHECKSUM_PLACEMENT: ChecksumPlacement = ChecksumPlacement::Prepend;
+        }
+
+        let bytes = Sha256::digest(b"message");
+        let appended = digits(Message::<A>::message(bytes));
+        let prepended = digits(Message::<Prepend>::message(bytes));
+        assert_ne!(appended, prepended);
+        assert_eq!(appended[64..], prepended[..3]);
+        assert_eq!(appended[..64], prepended[3..]);
+
+        let mut builder = MessageBuilder::<Prepend>::new();
+        builder.update(&bytes);
+        assert_eq!(digits(builder.finalize()), prepended);
+
+        let seed = Sha256::digest(b"seed");
+        let secret_key = SecretKey::<Prepend>::from_seed(&seed);
+        let public_key = PublicKey::from_secret(&secret_key);
+        let signature = Signature::sign(&secret_key, bytes);
+        assert!(signature.verify(&public_key, bytes));
+        assert!(!signature.verify(&public_key, Sha256::digest(b"other")));
+
+        let appended = Signature::sign(&SecretKey::<A>::from_seed(&seed), bytes);
+        assert_eq!(
+            appended.randomization_bytes(),
+            signature.randomization_bytes()
+        );
+        assert_ne!(appended.to_bytes(), signature.to_bytes());
+    }
+
+    #[test]
+    fn test_fit_digest() {
+        type Short = (U32, U16, U15, Sha256, ());
+        type Long = (U32, U48, U15, Sha256, ());
+
+        let full = Sha256::digest(b"data");
+        assert_eq!(fit_digest::<A>(&full), full);
+        assert_eq!(fit_digest::<Short>(&full)[..], full[..16]);
+
+        let long = fit_digest::<Long>(&full);
+        let first = Sha256::new().chain(full).chain([0, 0, 0, 0]).result();
+        let second = Sha256::new().chain(full).chain([0, 0, 0, 1]).result();
+        assert_eq!(long[..32], first[..]);
+        assert_eq!(long[32..], second[..16]);
+
+        assert_eq!(
+            digits(Message::<Long>::digest(b"data")),
+            digits(Message::<Long>::message(long)),
+        );
+        assert_eq!(
+            digits(Message::<Short>::with_prefix(b"da", b"ta")),
+            digits(Message::<Short>::message(fit_digest::<Short>(&full))),
+        );
+    }
+
+    #[test]
+    fn test_reuse_checksum() {
+        let mut bytes = [0x5a; 32];
+        let message = Message::<A>::message(GenericArray::clone_from_slice(&bytes));
+
+        // swapping the nibbles keeps the sum of the digits
+        bytes[3] = 0xa5;
+        let swapped = GenericArray::clone_from_slice(&bytes);
+        let reused = message.reuse_checksum(swapped).unwrap();
+        assert_eq!(digits(reused), digits(Message::<A>::message(swapped)));
+
+        bytes[3] = 0xa6;
+        assert!(message
+            .reuse_checksum(GenericArray::clone_from_slice(&bytes))
+            .is_none());
+        assert!(message.clone().inverse().reuse_checksum(swapped).is_none());
+    }
+
+    fn algebra<A>()
+    where
+        A: WOtsPlus<BlockLength = U32, MessageSize = U32, Digest = Sha256>,
+    {
+        let secret_key = SecretKey::<A>::from_seed(&Sha256::digest(b"algebra"));
+        let state = State::new(
+            secret_key.randomization().clone(),
+            secret_key.data().to_vec(),
+        );
+        let infinity = Message::infinity();
+        let tips = &state * &infinity;
+        assert!(&state * Message::infinity() == tips);
+        for i in 0..16u8 {
+            let message = Message::<A>::message(Sha256::digest(&[i]));
+            let signed = &state * message.clone();
+            assert!(&signed * message.clone().inverse() == tips);
+            assert!(&state * &infinity == tips);
+
+            // advancing in two steps is the same as advancing by the whole range
+            let (first, second) = message
+                .ranges
                 .iter()
-                .zip(rhs.ranges)
-                .map(|(block, range)| {
-                    self.randomization[range]
-                        .iter()
-                        .fold(block.clone(), |b, a| {
-                            let v = GenericArray::<u8, A::BlockLength>::generate(|i| a[i] ^ b[i]);
-                            A::Digest::new().chain(v).result()
-                        })
-                })
-                .collect(),
+                .map(|r| (0..(r.end / 2), (r.end / 2)..r.end))
+                .unzip();
+            let first = Message::<A> {
+                ranges: first,
+                phantom_data: PhantomData,
+            };
+            let second = Message::<A> {
+                ranges: second,
+                phantom_data: PhantomData,
+            };
+            assert!(&(&state * first) * second == signed);
+
+            let other = Message::<A>::message(Sha256::digest(&[i, 0xff]));
+            assert!(&signed * other.inverse() != tips);
         }
     }
+
+    #[test]
+    fn test_algebra() {
+        algebra::<(U32, U32, U15, Sha256, ())>();
+        algebra::<(U32, U32, U255, Sha256, ())>();
+    }
 }
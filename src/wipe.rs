@@ -0,0 +1,28 @@
+use core::{
+    hint,
+    sync::atomic::{self, Ordering},
+};
+
+// overwrite with the default value, the crate forbids unsafe code, so this is not
+// the volatile write of the `zeroize` crate, `black_box` and the fence keep the compiler
+// from removing the writes as dead stores, it is the best effort, not a guarantee
+pub(crate) fn wipe<T>(values: &mut [T])
+where
+    T: Default,
+{
+    values.iter_mut().for_each(|value| *value = T::default());
+    hint::black_box(&mut *values);
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::wipe;
+
+    #[test]
+    fn test_wipe() {
+        let mut values = vec![[7u8; 32]; 3];
+        wipe(&mut values);
+        assert_eq!(values, vec![[0u8; 32]; 3]);
+    }
+}
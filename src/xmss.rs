@@ -1,70 +1,798 @@
+use core::marker::PhantomData;
+use digest::{
+    generic_array::{GenericArray, ArrayLength},
+    Digest,
+};
+
+use super::error::{DecodeError, XmssError};
+use super::ct::ct_eq;
+
+/// The height limit of the trees, the number of leaves `2 ^ height` fits in `u64`
+pub const MAX_HEIGHT: usize = 63;
+
 pub trait XmssOperation<T> {
     fn operation(&self, height: usize, lhs: T, rhs: T) -> T;
 }
 
-pub struct XmssPath<T>(Vec<(T, bool)>);
+/// The node is `H(domain || height || lhs || rhs)`, where the height
+/// is big endian `u32`, it is omitted if the height is not included
+pub struct HashOperation<D>
+where
+    D: Digest,
+{
+    domain: Vec<u8>,
+    include_height: bool,
+    phantom_data: PhantomData<D>,
+}
+
+impl<D> HashOperation<D>
+where
+    D: Digest,
+{
+    pub fn new(domain: &[u8]) -> Self {
+        HashOperation {
+            domain: domain.to_vec(),
+            include_height: true,
+            phantom_data: PhantomData,
+        }
+    }
+
+    pub fn without_height(self) -> Self {
+        HashOperation {
+            domain: self.domain,
+            include_height: false,
+            phantom_data: PhantomData,
+        }
+    }
+}
+
+impl<D> XmssOperation<GenericArray<u8, D::OutputSize>> for HashOperation<D>
+where
+    D: Digest,
+{
+    fn operation(
+        &self,
+        height: usize,
+        lhs: GenericArray<u8, D::OutputSize>,
+        rhs: GenericArray<u8, D::OutputSize>,
+    ) -> GenericArray<u8, D::OutputSize> {
+        let d = D::new().chain(&self.domain);
+        let d = match self.include_height {
+            false => d,
+            true => d.chain((height as u32).to_be_bytes()),
+        };
+        d.chain(lhs).chain(rhs).result()
+    }
+}
+
+/// The operation applied to the children in sorted order rather than positional,
+/// as some Merkle conventions do, the path and `advance` give the same root with it
+pub struct Sorted<F>(pub F);
+
+impl<T, F> XmssOperation<T> for Sorted<F>
+where
+    T: Ord,
+    F: XmssOperation<T>,
+{
+    fn operation(&self, height: usize, lhs: T, rhs: T) -> T {
+        match self {
+            Sorted(f) if rhs < lhs => f.operation(height, rhs, lhs),
+            Sorted(f) => f.operation(height, lhs, rhs),
+        }
+    }
+}
+
+/// What happens to the last node of a level of an odd size, it is promoted
+/// to the next level without hashing, like `collapse` does,
+/// or hashed with its own copy as the sibling
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OddNode {
+    Promote,
+    Duplicate,
+}
+
+/// Siblings on the way from a leaf to the root, each of them is stored
+/// with its height and whether it is the left operand of the operation,
+/// a level where the node is promoted without a sibling is absent
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct XmssPath<T>(Vec<(usize, T, bool)>);
 
 impl<T> XmssPath<T> {
+    /// The path of the leaf `index` in a tree of `siblings.len()` height,
+    /// which has no promoted nodes, e.g. its size is a power of two,
+    /// the height is at most `MAX_HEIGHT`
+    pub fn from_index(index: u64, siblings: Vec<T>) -> Self {
+        assert!(siblings.len() <= MAX_HEIGHT);
+        XmssPath(
+            siblings
+                .into_iter()
+                .enumerate()
+                .map(|(height, other)| (height, other, (index >> height) & 1 == 1))
+                .collect(),
+        )
+    }
+
+    pub fn leaf_index(&self) -> u64 {
+        self.0
+            .iter()
+            .fold(0, |index, &(height, _, reverse)| match reverse {
+                false => index,
+                true => index | (1u64 << height),
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the path is of the leaf `index` in the full tree of the height,
+    /// there is a sibling on each level and the directions are the bits of the index
+    pub fn validate(&self, height: usize, index: u64) -> Result<(), XmssError> {
+        if self.0.len() != height || height > MAX_HEIGHT {
+            return Err(XmssError::HeightMismatch {
+                expected: height,
+                got: self.0.len(),
+            });
+        }
+        if self.0.iter().enumerate().any(|(i, &(h, _, _))| i != h) {
+            return Err(XmssError::MissingLevel);
+        }
+        let leaf_index = self.leaf_index();
+        if leaf_index != index {
+            return Err(XmssError::IndexMismatch {
+                expected: index,
+                got: leaf_index,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn siblings(&self) -> impl Iterator<Item = &T> {
+        self.0.iter().map(|(_, other, _)| other)
+    }
+
     pub fn advance<F>(self, item: T, f: &F) -> T
     where
         F: XmssOperation<T>,
     {
         self.0
             .into_iter()
-            .enumerate()
-            .fold(item, |item, (i, (other, reverse))| match reverse {
-                false => f.operation(i, item, other),
-                true => f.operation(i, other, item),
+            .fold(item, |item, (height, other, reverse)| match reverse {
+                false => f.operation(height, item, other),
+                true => f.operation(height, other, item),
+            })
+    }
+
+    /// Same as `advance`, but the path is kept, so it can be verified again or logged,
+    /// the operation takes the nodes by value, so each sibling is cloned once
+    pub fn advance_ref<F>(&self, item: &T, f: &F) -> T
+    where
+        F: XmssOperation<T>,
+        T: Clone,
+    {
+        self.0.iter().fold(
+            item.clone(),
+            |item, &(height, ref other, reverse)| match reverse {
+                false => f.operation(height, item, other.clone()),
+                true => f.operation(height, other.clone(), item),
+            },
+        )
+    }
+
+    /// Advance only through the siblings below `levels`, the result is the root
+    /// of the subtree `(levels, leaf_index >> levels)`, see `XmssTree::subtree_root`,
+    /// it is the `item` itself if `levels` is zero
+    pub fn advance_to<F>(self, item: T, levels: usize, f: &F) -> T
+    where
+        F: XmssOperation<T>,
+    {
+        self.0
+            .into_iter()
+            .take_while(|&(height, _, _)| height < levels)
+            .fold(item, |item, (height, other, reverse)| match reverse {
+                false => f.operation(height, item, other),
+                true => f.operation(height, other, item),
             })
     }
 }
 
+impl<N> XmssPath<GenericArray<u8, N>>
+where
+    N: ArrayLength<u8>,
+{
+    /// The directions packed in `ceil(len / 8)` bytes, the direction at the height `i`
+    /// is the bit `i % 8` of the byte `i / 8`, followed by the siblings,
+    /// fails on the path with promoted levels, e.g. of the tree of not a power of two size
+    pub fn to_bytes(&self) -> Result<Vec<u8>, XmssError> {
+        let height = self.0.len();
+        if !self.0.iter().enumerate().all(|(i, &(h, _, _))| i == h) {
+            return Err(XmssError::MissingLevel);
+        }
+        let mut bytes = vec![0; height.div_ceil(8)];
+        self.0
+            .iter()
+            .filter(|&&(_, _, reverse)| reverse)
+            .for_each(|&(h, _, _)| bytes[h / 8] |= 1 << (h % 8));
+        self.0
+            .iter()
+            .for_each(|(_, other, _)| bytes.extend_from_slice(other.as_ref()));
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8], height: usize) -> Result<Self, DecodeError> {
+        let directions = height.div_ceil(8);
+        let expected = directions + height * N::USIZE;
+        if bytes.len() != expected {
+            return Err(DecodeError::WrongLength {
+                expected,
+                got: bytes.len(),
+            });
+        }
+        Ok(XmssPath(
+            bytes[directions..]
+                .chunks(N::USIZE)
+                .map(GenericArray::clone_from_slice)
+                .enumerate()
+                .map(|(h, other)| (h, other, (bytes[h / 8] >> (h % 8)) & 1 == 1))
+                .collect(),
+        ))
+    }
+
+    /// The hex of `to_bytes`
+    pub fn to_hex(&self) -> Result<String, XmssError> {
+        self.to_bytes().map(hex::encode)
+    }
+
+    /// The path from the hex of `to_bytes`, in any case, the odd length is invalid
+    /// at the position of the missing digit
+    pub fn from_hex(hex: &str, height: usize) -> Result<Self, DecodeError> {
+        let bytes = hex::decode(hex).map_err(|error| match error {
+            hex::FromHexError::InvalidHexCharacter { index: index, .. } => {
+                DecodeError::InvalidHex { position: index }
+            },
+            _ => DecodeError::InvalidHex {
+                position: hex.len(),
+            },
+        })?;
+        Self::from_bytes(&bytes, height)
+    }
+}
+
 #[derive(Clone)]
 pub struct XmssTree<T>(pub Vec<T>);
 
 impl<T> XmssTree<T> {
-    pub fn path<F>(self, item: T, f: &F) -> (T, XmssPath<T>)
+    fn height(&self) -> usize {
+        use core::mem;
+
+        assert!(!self.0.is_empty());
+        mem::size_of::<usize>() * 8 - ((self.0.len() - 1).leading_zeros() as usize)
+    }
+
+    fn fold_level<F>(data: Vec<T>, height: usize, f: &F) -> Vec<T>
     where
         F: XmssOperation<T>,
-        T: Eq,
     {
-        let _ = (item, f);
-        unimplemented!()
+        let capacity = data.len() / 2 + 1;
+        let (state, mut new) = data.into_iter().fold(
+            (None, Vec::with_capacity(capacity)),
+            |(accumulator, mut new), item| match accumulator {
+                None => (Some(item), new),
+                Some(left) => {
+                    new.push(f.operation(height, left, item));
+                    (None, new)
+                },
+            },
+        );
+        match state {
+            None => new,
+            Some(item) => {
+                new.push(item);
+                new
+            },
+        }
     }
 
-    pub fn collapse<F>(self, f: &F) -> T
+    fn fold_level_with<F>(data: Vec<T>, height: usize, odd: OddNode, f: &F) -> Vec<T>
     where
         F: XmssOperation<T>,
+        T: Clone,
     {
-        use core::mem;
+        let mut data = data;
+        if odd == OddNode::Duplicate && data.len() % 2 == 1 {
+            data.push(data[data.len() - 1].clone());
+        }
+        Self::fold_level(data, height, f)
+    }
 
+    /// Same as `collapse`, but the odd node is handled by the policy
+    pub fn collapse_with<F>(self, odd: OddNode, f: &F) -> T
+    where
+        F: XmssOperation<T>,
+        T: Clone,
+    {
+        let height = self.height();
         let XmssTree(data) = self;
-
-        assert!(!data.is_empty());
-        let height = mem::size_of::<usize>() * 8 - ((data.len() - 1).leading_zeros() as usize);
-        let mut data = (0..height).fold(data, |data, index| {
-            let capacity = data.len() / 2 + 1;
-            let (state, mut new) = data.into_iter().fold(
-                (None, Vec::with_capacity(capacity)),
-                |(accumulator, mut new), item| match accumulator {
-                    None => (Some(item), new),
-                    Some(left) => {
-                        new.push(f.operation(index, left, item));
-                        (None, new)
-                    },
-                },
-            );
-            match state {
-                None => new,
-                Some(item) => {
-                    new.push(item);
-                    new
-                },
-            }
+        let mut data = (0..height).fold(data, |data, height| {
+            Self::fold_level_with(data, height, odd, f)
         });
         assert!(data.len() == 1);
         data.pop().unwrap()
     }
+
+    /// Same as `root_and_path`, but the odd node is handled by the policy,
+    /// the duplicated node is its own sibling in the path
+    pub fn root_and_path_with<F>(self, index: usize, odd: OddNode, f: &F) -> (T, XmssPath<T>)
+    where
+        F: XmssOperation<T>,
+        T: Clone,
+    {
+        let height = self.height();
+        let XmssTree(data) = self;
+        assert!(index < data.len());
+
+        let (mut data, _, path) = (0..height).fold(
+            (data, index, Vec::with_capacity(height)),
+            |(data, index, mut path), height| {
+                match (data.get(index ^ 1), odd) {
+                    (Some(other), _) => path.push((height, other.clone(), index & 1 == 1)),
+                    (None, OddNode::Duplicate) => path.push((height, data[index].clone(), false)),
+                    (None, OddNode::Promote) => (),
+                }
+                (Self::fold_level_with(data, height, odd, f), index / 2, path)
+            },
+        );
+        assert!(data.len() == 1);
+        (data.pop().unwrap(), XmssPath(path))
+    }
+
+    /// The positions of all leaves equal to the item
+    pub fn find_all(&self, item: &T) -> Vec<usize>
+    where
+        T: Eq,
+    {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|&(_, leaf)| leaf == item)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// The root and the path of the leaf at the index, unlike `path` it is not
+    /// ambiguous if the leaves are not unique, prefer it
+    pub fn path_at<F>(&self, index: usize, f: &F) -> (T, XmssPath<T>)
+    where
+        F: XmssOperation<T>,
+        T: Clone,
+    {
+        self.clone().root_and_path(index, f)
+    }
+
+    /// The path of the first leaf equal to the item, see `path_at`
+    pub fn path<F>(self, item: T, f: &F) -> (T, XmssPath<T>)
+    where
+        F: XmssOperation<T>,
+        T: Eq + Clone,
+    {
+        let index = self
+            .0
+            .iter()
+            .position(|leaf| *leaf == item)
+            .expect("the item should be a leaf of the tree");
+        self.root_and_path(index, f)
+    }
+
+    pub fn path_ref<F>(&self, item: &T, f: &F) -> (T, XmssPath<T>)
+    where
+        F: XmssOperation<T>,
+        T: Eq + Clone,
+    {
+        self.clone().path(item.clone(), f)
+    }
+
+    /// Same as `path_ref` of `leaf_fn(input)`, the leaf function should be the one
+    /// the tree is built with, e.g. `|public_key| public_key.leaf(f)`
+    pub fn path_for_input<I, G, F>(&self, input: I, leaf_fn: G, f: &F) -> (T, XmssPath<T>)
+    where
+        G: FnOnce(I) -> T,
+        F: XmssOperation<T>,
+        T: Eq + Clone,
+    {
+        self.path_ref(&leaf_fn(input), f)
+    }
+
+    /// The path of the leaf 0, where a signer starts
+    pub fn initial_path<F>(&self, f: &F) -> XmssPath<T>
+    where
+        F: XmssOperation<T>,
+        T: Clone,
+    {
+        let (_, path) = self.clone().root_and_path(0, f);
+        path
+    }
+
+    /// The root and the path of the leaf `index`, computed in a single pass,
+    /// collecting the sibling on each level while the level is folded
+    pub fn root_and_path<F>(self, index: usize, f: &F) -> (T, XmssPath<T>)
+    where
+        F: XmssOperation<T>,
+        T: Clone,
+    {
+        self.root_and_path_with(index, OddNode::Promote, f)
+    }
+
+    /// The root of the subtree at `level` above the leaves with position `index`
+    /// in that level, the same node `collapse` computes on the way to the root,
+    /// the sibling at `level` of the leaf `i` is the subtree `(level, (i >> level) ^ 1)`
+    pub fn subtree_root<F>(&self, level: usize, index: usize, f: &F) -> T
+    where
+        F: XmssOperation<T>,
+        T: Clone,
+    {
+        let start = index << level;
+        assert!(start < self.0.len(), "the subtree should exist");
+        let end = self.0.len().min((index + 1) << level);
+        XmssTree(self.0[start..end].to_vec()).collapse(f)
+    }
+
+    /// The root of the tree from the roots of its subtrees at `level`, in the order
+    /// of the leaves, the subtrees are `subtree_root(level, i)`, e.g. built on different
+    /// machines, all of them are full except maybe the last one
+    pub fn from_subtrees<F>(subtree_roots: Vec<T>, level: usize, f: &F) -> T
+    where
+        F: XmssOperation<T>,
+    {
+        let tree = XmssTree(subtree_roots);
+        let height = tree.height();
+        let XmssTree(data) = tree;
+        let mut data =
+            (level..(level + height)).fold(data, |data, height| Self::fold_level(data, height, f));
+        assert!(data.len() == 1);
+        data.pop().unwrap()
+    }
+
+    pub fn collapse<F>(self, f: &F) -> T
+    where
+        F: XmssOperation<T>,
+    {
+        let height = self.height();
+        let XmssTree(data) = self;
+        let mut data = (0..height).fold(data, |data, index| Self::fold_level(data, index, f));
+        assert!(data.len() == 1);
+        data.pop().unwrap()
+    }
+
+    /// The root of the full tree of the height where the leaves absent in `present`
+    /// are the default, the subtrees of defaults are computed once per level,
+    /// so it takes `O(present.len() * height)` operations, the indices are unique
+    pub fn sparse<F>(present: &[(usize, T)], default: T, height: usize, f: &F) -> T
+    where
+        T: Clone,
+        F: XmssOperation<T>,
+    {
+        assert!(height < usize::BITS as usize);
+        let mut leaves = present.to_vec();
+        leaves.sort_by_key(|&(index, _)| index);
+        assert!(
+            leaves.windows(2).all(|pair| pair[0].0 != pair[1].0),
+            "the indices should be unique",
+        );
+        assert!(
+            leaves.last().is_none_or(|&(index, _)| index >> height == 0),
+            "the index should be less than `2 ^ height`",
+        );
+
+        let defaults = (0..height).fold(vec![default], |mut defaults, level| {
+            let node = defaults[level].clone();
+            defaults.push(f.operation(level, node.clone(), node));
+            defaults
+        });
+        Self::sparse_node(&leaves, &defaults, height, 0, f)
+    }
+
+    // the root of the subtree at the position, the leaves are sorted and inside it
+    fn sparse_node<F>(
+        leaves: &[(usize, T)],
+        defaults: &[T],
+        level: usize,
+        position: usize,
+        f: &F,
+    ) -> T
+    where
+        T: Clone,
+        F: XmssOperation<T>,
+    {
+        match leaves {
+            [] => defaults[level].clone(),
+            [(_, leaf)] if level == 0 => leaf.clone(),
+            _ => {
+                let middle = (2 * position + 1) << (level - 1);
+                let split = leaves.partition_point(|&(index, _)| index < middle);
+                let (left, right) = leaves.split_at(split);
+                let left = Self::sparse_node(left, defaults, level - 1, 2 * position, f);
+                let right = Self::sparse_node(right, defaults, level - 1, 2 * position + 1, f);
+                f.operation(level - 1, left, right)
+            },
+        }
+    }
+
+    /// The bytes of the root, to feed it as a leaf into a parent structure
+    pub fn root_bytes<F>(&self, f: &F) -> Vec<u8>
+    where
+        T: Clone + AsRef<[u8]>,
+        F: XmssOperation<T>,
+    {
+        XmssTree(self.0.clone()).collapse(f).as_ref().to_vec()
+    }
+
+    pub fn cache<F>(self, f: &F) -> CachedXmssTree<T>
+    where
+        F: XmssOperation<T>,
+        T: Clone,
+    {
+        let height = self.height();
+        let XmssTree(data) = self;
+        let mut layers = Vec::with_capacity(height + 1);
+        layers.push(data);
+        for height in 0..height {
+            let next = Self::fold_level(layers[height].clone(), height, f);
+            layers.push(next);
+        }
+        CachedXmssTree(layers)
+    }
+}
+
+/// Compute the leaves, e.g. the compressed public keys of the one time keys, the leaf
+/// function gets the operation `f` to compress with, with the `parallel` feature
+/// they are computed in several std scoped threads, the tree is the same in both cases
+pub fn build_tree<I, T, F, L>(items: I, leaf_fn: L, f: &F) -> XmssTree<T>
+where
+    I: IntoIterator,
+    I::Item: Send,
+    T: Send,
+    F: Sync,
+    L: Fn(I::Item, &F) -> T + Sync,
+{
+    XmssTree(par_map(items, |item| leaf_fn(item, f)))
+}
+
+// map in order, with the `parallel` feature the items are split into a chunk per thread,
+// the panic of a worker is resumed with its own payload
+pub(crate) fn par_map<I, T, L>(items: I, map_fn: L) -> Vec<T>
+where
+    I: IntoIterator,
+    I::Item: Send,
+    T: Send,
+    L: Fn(I::Item) -> T + Sync,
+{
+    #[cfg(feature = "parallel")]
+    {
+        use std::{panic, thread};
+
+        let items = items.into_iter().collect::<Vec<_>>();
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = items.len().div_ceil(threads).max(1);
+        let mut items = items.into_iter();
+        let chunks = (0..threads)
+            .map(|_| items.by_ref().take(chunk).collect::<Vec<_>>())
+            .filter(|chunk| !chunk.is_empty())
+            .collect::<Vec<_>>();
+        let map_fn = &map_fn;
+        thread::scope(|scope| {
+            chunks
+                .into_iter()
+                .map(|chunk| scope.spawn(move || chunk.into_iter().map(map_fn).collect::<Vec<_>>()))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        })
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.into_iter().map(map_fn).collect()
+    }
+}
+
+/// Whether the two leaves with their paths lead to the same root, compared in constant
+/// time, the paths of different lengths are of different trees, so they never match
+pub fn same_root<T, F>(a: (T, XmssPath<T>), b: (T, XmssPath<T>), f: &F) -> bool
+where
+    T: AsRef<[u8]>,
+    F: XmssOperation<T>,
+{
+    let ((a, a_path), (b, b_path)) = (a, b);
+    if a_path.len() != b_path.len() {
+        return false;
+    }
+    ct_eq(a_path.advance(a, f).as_ref(), b_path.advance(b, f).as_ref())
+}
+
+/// The parent of the two subtree roots at `height`, the height of the children,
+/// e.g. the halves built independently are joined at `height - 1` of the whole tree
+pub fn join<T, F>(height: usize, left_root: T, right_root: T, f: &F) -> T
+where
+    F: XmssOperation<T>,
+{
+    f.operation(height, left_root, right_root)
+}
+
+/// Collapse each tree and then the tree of their roots, with the `parallel` feature
+/// the trees are collapsed in several threads. It is a different structure than
+/// the single tree over all leaves, the roots are hashed from the height 0 again,
+/// so the super root differs from it even if all trees are full and of the same height
+pub fn forest_root<T, F>(trees: Vec<XmssTree<T>>, f: &F) -> T
+where
+    T: Send,
+    F: XmssOperation<T> + Sync,
+{
+    build_tree(trees, |tree, f| tree.collapse(f), f).collapse(f)
+}
+
+// the incremental treehash, the leaves are pushed from the left, the stack holds the root
+// of each complete subtree not yet joined, at most one node per height, the highest first
+pub(crate) struct Treehash<T> {
+    stack: Vec<(usize, T)>,
+}
+
+impl<T> Treehash<T> {
+    pub fn new() -> Self {
+        Treehash { stack: Vec::new() }
+    }
+
+    pub fn from_stack(stack: Vec<(usize, T)>) -> Self {
+        Treehash { stack }
+    }
+
+    pub fn stack(&self) -> &[(usize, T)] {
+        &self.stack
+    }
+
+    pub fn push<F>(&mut self, leaf: T, f: &F)
+    where
+        F: XmssOperation<T>,
+    {
+        let mut node = (0, leaf);
+        while self.stack.last().map(|&(height, _)| height) == Some(node.0) {
+            let (height, left) = self.stack.pop().unwrap();
+            node = (height + 1, f.operation(height, left, node.1));
+        }
+        self.stack.push(node);
+    }
+
+    // the root, `None` if nothing is pushed, the incomplete subtrees are merged
+    // from the lowest, the odd node is promoted as `collapse` does
+    pub fn finish<F>(mut self, f: &F) -> Option<T>
+    where
+        F: XmssOperation<T>,
+    {
+        let (_, mut root) = self.stack.pop()?;
+        while let Some((height, left)) = self.stack.pop() {
+            root = f.operation(height, left, root);
+        }
+        Some(root)
+    }
+}
+
+// the root of the subtree of `2 ^ level` leaves at the position
+pub(crate) fn treehash<T, L, F>(leaf_fn: &L, level: usize, position: u64, f: &F) -> T
+where
+    L: Fn(u64) -> T,
+    F: XmssOperation<T>,
+{
+    let start = position << level;
+    let mut treehash = Treehash::from_stack(Vec::with_capacity(level + 1));
+    for offset in 0..(1 << level) {
+        treehash.push(leaf_fn(start + offset), f);
+    }
+    treehash.finish(f).unwrap()
+}
+
+/// The full tree of `2 ^ height` leaves generated on demand, nothing is stored,
+/// each node is computed by treehash, it takes `O(height)` memory,
+/// but each path takes all `2 ^ height` leaf generations again
+pub struct LazyXmssTree<T, L>
+where
+    L: Fn(u64) -> T,
+{
+    height: usize,
+    leaf_fn: L,
+}
+
+impl<T, L> LazyXmssTree<T, L>
+where
+    L: Fn(u64) -> T,
+{
+    /// The height is at most `MAX_HEIGHT`
+    pub fn new(height: usize, leaf_fn: L) -> Result<Self, XmssError> {
+        if height > MAX_HEIGHT {
+            return Err(XmssError::BadHeight { height });
+        }
+        Ok(LazyXmssTree { height, leaf_fn })
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn node<F>(&self, level: usize, position: u64, f: &F) -> T
+    where
+        F: XmssOperation<T>,
+    {
+        treehash(&self.leaf_fn, level, position, f)
+    }
+
+    pub fn root<F>(&self, f: &F) -> T
+    where
+        F: XmssOperation<T>,
+    {
+        self.node(self.height, 0, f)
+    }
+
+    /// The path of the leaf, the root of the sibling subtree on each level
+    pub fn path<F>(&self, index: u64, f: &F) -> XmssPath<T>
+    where
+        F: XmssOperation<T>,
+    {
+        assert!(index < 1 << self.height);
+        XmssPath(
+            (0..self.height)
+                .map(|height| {
+                    let position = index >> height;
+                    (
+                        height,
+                        self.node(height, position ^ 1, f),
+                        position & 1 == 1,
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+/// The tree with all its internal layers, the path is a lookup of siblings,
+/// takes about twice the memory of the leaves
+#[derive(Clone)]
+pub struct CachedXmssTree<T>(Vec<Vec<T>>);
+
+impl<T> CachedXmssTree<T> {
+    pub fn height(&self) -> usize {
+        self.0.len() - 1
+    }
+
+    pub fn leaves(&self) -> &[T] {
+        self.0[0].as_ref()
+    }
+
+    pub fn root(&self) -> &T {
+        &self.0[self.height()][0]
+    }
+
+    pub fn path(&self, index: u64) -> XmssPath<T>
+    where
+        T: Clone,
+    {
+        assert!(index < self.0[0].len() as u64);
+        let index = index as usize;
+        XmssPath(
+            self.0[..self.height()]
+                .iter()
+                .enumerate()
+                .filter_map(|(height, layer)| {
+                    let index = index >> height;
+                    layer
+                        .get(index ^ 1)
+                        .map(|other| (height, other.clone(), index & 1 == 1))
+                })
+                .collect(),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -82,3 +810,498 @@ fn test_xmss_tree_collapse() {
         assert_eq!(x, n * (n - 1) / 2);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{XmssOperation, XmssTree};
+
+    // not commutative and depends on the height, so a wrong order or height is detected
+    pub struct Concat;
+
+    impl XmssOperation<String> for Concat {
+        fn operation(&self, height: usize, lhs: String, rhs: String) -> String {
+            format!("({}{}{})", lhs, height, rhs)
+        }
+    }
+
+    #[test]
+    fn test_hash_operation() {
+        use digest::Digest;
+        use super::super::sha256::Sha256;
+        use super::HashOperation;
+
+        let lhs = Sha256::digest(b"lhs");
+        let rhs = Sha256::digest(b"rhs");
+        let f = HashOperation::<Sha256>::new(b"node");
+        assert_eq!(
+            f.operation(3, lhs, rhs),
+            Sha256::new()
+                .chain(b"node")
+                .chain([0, 0, 0, 3])
+                .chain(lhs)
+                .chain(rhs)
+                .result(),
+        );
+        let f = f.without_height();
+        assert_eq!(
+            f.operation(3, lhs, rhs),
+            Sha256::new().chain(b"node").chain(lhs).chain(rhs).result(),
+        );
+    }
+
+    #[test]
+    fn test_xmss_tree_root_bytes() {
+        let tree = XmssTree((0..5).map(|i| i.to_string()).collect::<Vec<_>>());
+        assert_eq!(tree.root_bytes(&Concat), b"(((001)1(203))24)");
+        assert_eq!(
+            tree.root_bytes(&Concat),
+            tree.collapse(&Concat).into_bytes()
+        );
+    }
+
+    #[test]
+    fn test_xmss_tree_path_for_input() {
+        let tree = XmssTree((0..6).map(|i| i.to_string()).collect::<Vec<_>>());
+        let (root, path) = tree.path_for_input(4usize, |i| i.to_string(), &Concat);
+        assert_eq!(root, tree.clone().collapse(&Concat));
+        assert_eq!(path.leaf_index(), 4);
+        assert_eq!(path.advance("4".to_string(), &Concat), root);
+    }
+
+    #[test]
+    fn test_xmss_path_advance_ref() {
+        for &n in &[1, 5, 8] {
+            let tree = XmssTree((0..n).map(|i| i.to_string()).collect::<Vec<_>>());
+            for index in 0..n {
+                let (root, path) = tree.path_at(index, &Concat);
+                let leaf = index.to_string();
+                assert_eq!(path.advance_ref(&leaf, &Concat), root);
+                assert_eq!(
+                    path.advance_ref(&leaf, &Concat),
+                    path.clone().advance(leaf, &Concat)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_xmss_path_validate() {
+        use super::{XmssPath, super::XmssError};
+
+        let path = XmssPath::from_index(5, vec![0usize; 3]);
+        assert_eq!(path.validate(3, 5), Ok(()));
+        assert_eq!(
+            path.validate(4, 5),
+            Err(XmssError::HeightMismatch {
+                expected: 4,
+                got: 3,
+            }),
+        );
+        assert_eq!(
+            path.validate(3, 4),
+            Err(XmssError::IndexMismatch {
+                expected: 4,
+                got: 5,
+            }),
+        );
+        assert!(path.validate(3, 13).is_err());
+
+        // the path of the promoted leaf has no sibling on the level 0
+        let (_, path) = XmssTree((0..5).collect::<Vec<usize>>()).path_at(4, &());
+        assert_eq!(path.validate(path.len(), 4), Err(XmssError::MissingLevel));
+    }
+
+    #[test]
+    fn test_lazy_xmss_tree() {
+        use super::{LazyXmssTree, MAX_HEIGHT, super::XmssError};
+
+        for &height in &[0, 1, 3] {
+            let lazy = LazyXmssTree::new(height, |index| index.to_string()).unwrap();
+            let tree = XmssTree((0..(1 << height)).map(|i: u64| i.to_string()).collect());
+            let root = lazy.root(&Concat);
+            assert_eq!(root, tree.clone().collapse(&Concat));
+            for index in 0..(1 << height) {
+                let path = lazy.path(index, &Concat);
+                assert_eq!(path, tree.path_at(index as usize, &Concat).1);
+                assert_eq!(path.advance(index.to_string(), &Concat), root);
+            }
+        }
+        assert!(LazyXmssTree::new(MAX_HEIGHT, |index| index.to_string()).is_ok());
+        assert_eq!(
+            LazyXmssTree::new(64, |index| index.to_string()).err(),
+            Some(XmssError::BadHeight { height: 64 }),
+        );
+    }
+
+    #[test]
+    fn test_treehash_accumulator() {
+        use super::Treehash;
+
+        assert_eq!(Treehash::<String>::new().finish(&Concat), None);
+        for count in 1..=9 {
+            let mut treehash = Treehash::new();
+            (0..count).for_each(|i| treehash.push(i.to_string(), &Concat));
+            assert!(treehash.stack().len() <= 4);
+            let tree = XmssTree((0..count).map(|i| i.to_string()).collect());
+            assert_eq!(treehash.finish(&Concat), Some(tree.collapse(&Concat)));
+        }
+    }
+
+    #[test]
+    fn test_xmss_tree_sparse() {
+        let default = "_".to_string();
+        for &height in &[0, 1, 3, 4] {
+            let present = [(5usize, "a"), (0, "b"), (15, "c"), (6, "d")]
+                .iter()
+                .filter(|&&(index, _)| index >> height == 0)
+                .map(|&(index, leaf)| (index, leaf.to_string()))
+                .collect::<Vec<_>>();
+            let mut dense = vec![default.clone(); 1 << height];
+            present
+                .iter()
+                .for_each(|&(index, ref leaf)| dense[index] = leaf.clone());
+            assert_eq!(
+                XmssTree::sparse(&present, default.clone(), height, &Concat),
+                XmssTree(dense).collapse(&Concat),
+            );
+        }
+        assert_eq!(XmssTree::sparse(&[], default.clone(), 1, &Concat), "(_0_)");
+    }
+
+    #[test]
+    fn test_same_root() {
+        use super::same_root;
+
+        let leaves = (0..8).map(|i| i.to_string()).collect::<Vec<_>>();
+        let tree = XmssTree(leaves.clone());
+        let (_, a) = tree.path_at(1, &Concat);
+        let (_, b) = tree.path_at(6, &Concat);
+        assert!(same_root(
+            ("1".to_string(), a.clone()),
+            ("6".to_string(), b.clone()),
+            &Concat
+        ));
+        assert!(!same_root(
+            ("2".to_string(), a.clone()),
+            ("6".to_string(), b),
+            &Concat
+        ));
+
+        let (_, short) = XmssTree(leaves[..4].to_vec()).path_at(1, &Concat);
+        assert!(!same_root(
+            ("1".to_string(), a),
+            ("1".to_string(), short),
+            &Concat
+        ));
+    }
+
+    #[test]
+    fn test_xmss_tree_subtree_root() {
+        for &n in &[1, 3, 5, 16, 21] {
+            let leaves = (0..n).map(|i| i.to_string()).collect::<Vec<_>>();
+            let tree = XmssTree(leaves.clone());
+            let height = tree.height();
+            assert_eq!(
+                tree.subtree_root(height, 0, &Concat),
+                tree.clone().collapse(&Concat)
+            );
+            for index in 0..n {
+                let (_, path) = tree.clone().root_and_path(index, &Concat);
+                for &(level, ref other, _) in &path.0 {
+                    let subtree = tree.subtree_root(level, (index >> level) ^ 1, &Concat);
+                    assert_eq!(subtree, *other);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_xmss_path_advance_to() {
+        for &n in &[1, 4, 13] {
+            let tree = XmssTree((0..n).map(|i| i.to_string()).collect::<Vec<_>>());
+            let height = tree.height();
+            for index in 0..n {
+                let (root, path) = tree.clone().root_and_path(index, &Concat);
+                let leaf = tree.0[index].clone();
+                assert_eq!(path.clone().advance_to(leaf.clone(), 0, &Concat), leaf);
+                for levels in 1..=height {
+                    assert_eq!(
+                        path.clone().advance_to(leaf.clone(), levels, &Concat),
+                        tree.subtree_root(levels, index >> levels, &Concat),
+                    );
+                }
+                assert_eq!(path.advance_to(leaf, height, &Concat), root);
+            }
+        }
+    }
+
+    #[test]
+    fn test_xmss_path_tall_index() {
+        use super::XmssPath;
+
+        for &index in &[(1u64 << 32) - 1, 1 << 32, (1 << 32) + 5, !0 >> 1] {
+            let height = 64 - index.leading_zeros() as usize;
+            let path = XmssPath::from_index(index, vec![0usize; height]);
+            assert_eq!(path.len(), height);
+            assert_eq!(path.leaf_index(), index);
+        }
+    }
+
+    #[test]
+    fn test_xmss_tree_from_subtrees() {
+        use super::join;
+
+        for &n in &[1usize, 8, 13, 16] {
+            let tree = XmssTree((0..n).map(|i| i.to_string()).collect::<Vec<_>>());
+            let root = tree.clone().collapse(&Concat);
+            for level in 0..=tree.height() {
+                let roots = (0..n.div_ceil(1 << level))
+                    .map(|i| tree.subtree_root(level, i, &Concat))
+                    .collect();
+                assert_eq!(XmssTree::from_subtrees(roots, level, &Concat), root);
+            }
+        }
+
+        let tree = XmssTree((0..16).map(|i| i.to_string()).collect::<Vec<_>>());
+        let left = XmssTree(tree.0[..8].to_vec()).collapse(&Concat);
+        let right = XmssTree(tree.0[8..].to_vec()).collapse(&Concat);
+        assert_eq!(join(3, left, right, &Concat), tree.collapse(&Concat));
+    }
+
+    #[test]
+    fn test_xmss_path_bytes() {
+        use digest::{
+            generic_array::{GenericArray, typenum::U32},
+            Digest,
+        };
+        use super::super::{sha256::Sha256, XmssError};
+        use super::{HashOperation, XmssPath};
+
+        let node = HashOperation::<Sha256>::new(b"node");
+        let leaves = (0..16u8).map(|i| Sha256::digest(&[i])).collect::<Vec<_>>();
+        for index in [0, 5, 10, 15] {
+            let (root, path) = XmssTree(leaves.clone()).root_and_path(index, &node);
+            let bytes = path.to_bytes().unwrap();
+            assert_eq!(bytes.len(), 1 + 4 * 32);
+            assert_eq!(bytes[0], index as u8);
+            let decoded = XmssPath::from_bytes(&bytes, 4).unwrap();
+            assert_eq!(decoded, path);
+            assert_eq!(decoded.advance(leaves[index], &node), root);
+            assert!(XmssPath::<GenericArray<u8, U32>>::from_bytes(&bytes, 3).is_err());
+        }
+
+        let leaves = (0..512u16)
+            .map(|i| Sha256::digest(&i.to_be_bytes()))
+            .collect::<Vec<_>>();
+        let (root, path) = XmssTree(leaves.clone()).root_and_path(300, &node);
+        let bytes = path.to_bytes().unwrap();
+        assert_eq!(bytes[..2], [0x2c, 0x01]);
+        let decoded = XmssPath::from_bytes(&bytes, 9).unwrap();
+        assert_eq!(decoded.leaf_index(), 300);
+        assert_eq!(decoded.advance(leaves[300], &node), root);
+
+        // the last leaf of 5 is promoted over the levels 0 and 1
+        let (_, path) = XmssTree(leaves[..5].to_vec()).root_and_path(4, &node);
+        assert_eq!(path.to_bytes(), Err(XmssError::MissingLevel));
+        assert_eq!(path.to_hex(), Err(XmssError::MissingLevel));
+    }
+
+    #[test]
+    fn test_xmss_path_hex() {
+        use digest::{
+            generic_array::{GenericArray, typenum::U32},
+            Digest,
+        };
+        use super::super::{sha256::Sha256, DecodeError};
+        use super::{HashOperation, XmssPath};
+
+        type Path = XmssPath<GenericArray<u8, U32>>;
+
+        let node = HashOperation::<Sha256>::new(b"node");
+        let leaves = (0..8u8).map(|i| Sha256::digest(&[i])).collect::<Vec<_>>();
+        let (_, path) = XmssTree(leaves).root_and_path(5, &node);
+        let hex = path.to_hex().unwrap();
+        assert_eq!(hex, hex::encode(path.to_bytes().unwrap()));
+        assert_eq!(hex[..2], *"05");
+        assert_eq!(Path::from_hex(&hex, 3), Ok(path.clone()));
+        assert_eq!(Path::from_hex(&hex.to_uppercase(), 3), Ok(path));
+
+        assert_eq!(
+            Path::from_hex(&hex[1..], 3),
+            Err(DecodeError::InvalidHex {
+                position: hex.len() - 1,
+            }),
+        );
+        let mut bad = hex.clone();
+        bad.replace_range(6..7, "x");
+        assert_eq!(
+            Path::from_hex(&bad, 3),
+            Err(DecodeError::InvalidHex { position: 6 })
+        );
+        assert_eq!(
+            Path::from_hex(&hex[2..], 3),
+            Err(DecodeError::WrongLength {
+                expected: 1 + 3 * 32,
+                got: 3 * 32,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_xmss_tree_policy() {
+        use super::{OddNode, Sorted};
+
+        for &n in &[1usize, 5, 8, 11] {
+            let leaves = (0..n)
+                .map(|i| ((i * 7) % 11).to_string())
+                .collect::<Vec<_>>();
+            let tree = XmssTree(leaves.clone());
+            let promoted = tree.clone().collapse(&Concat);
+            assert_eq!(
+                tree.clone().collapse_with(OddNode::Promote, &Concat),
+                promoted
+            );
+            let duplicated = tree.clone().collapse_with(OddNode::Duplicate, &Concat);
+            assert_eq!(duplicated == promoted, n.is_power_of_two());
+            let sorted = tree.clone().collapse(&Sorted(Concat));
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                for &odd in &[OddNode::Promote, OddNode::Duplicate] {
+                    let (root, path) = tree.clone().root_and_path_with(index, odd, &Concat);
+                    assert_eq!(root, tree.clone().collapse_with(odd, &Concat));
+                    assert_eq!(path.clone().advance(leaf.clone(), &Concat), root);
+                    assert_eq!(path.leaf_index(), index as u64);
+                }
+                let (root, path) = tree.clone().root_and_path(index, &Sorted(Concat));
+                assert_eq!(root, sorted);
+                assert_eq!(path.advance(leaf.clone(), &Sorted(Concat)), sorted);
+            }
+        }
+        assert_eq!(
+            XmssTree(vec!["b".to_string(), "a".to_string()]).collapse(&Sorted(Concat)),
+            "(a0b)",
+        );
+    }
+
+    #[test]
+    fn test_forest_root() {
+        use super::forest_root;
+
+        let trees = [3, 4, 1]
+            .iter()
+            .map(|&n| XmssTree((0..n).map(|i| i.to_string()).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        let roots = trees.iter().map(|t| t.clone().collapse(&Concat)).collect();
+        assert_eq!(
+            forest_root(trees.clone(), &Concat),
+            XmssTree(roots).collapse(&Concat)
+        );
+        assert_eq!(forest_root(trees, &Concat), "((((001)12)0((001)1(203)))10)");
+    }
+
+    #[test]
+    fn test_xmss_tree_find_all() {
+        let tree = XmssTree(
+            ["a", "b", "a", "c", "a"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        );
+        assert_eq!(tree.find_all(&"a".to_string()), [0, 2, 4]);
+        assert_eq!(tree.find_all(&"c".to_string()), [3]);
+        assert!(tree.find_all(&"d".to_string()).is_empty());
+
+        let (root, first) = tree.path_ref(&"a".to_string(), &Concat);
+        assert_eq!(tree.path_at(0, &Concat), (root.clone(), first.clone()));
+        for index in tree.find_all(&"a".to_string()) {
+            let (r, path) = tree.path_at(index, &Concat);
+            assert_eq!(r, root);
+            assert_eq!(path.leaf_index(), index as u64);
+            assert_eq!(path.clone().advance("a".to_string(), &Concat), root);
+            assert_eq!(path == first, index == 0);
+        }
+    }
+
+    #[test]
+    fn test_build_tree() {
+        use super::build_tree;
+
+        let tree = build_tree(0..37, |i: usize, _: &Concat| (i * 3).to_string(), &Concat);
+        assert_eq!(
+            tree.0,
+            (0..37).map(|i| (i * 3).to_string()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            tree.collapse(&Concat),
+            XmssTree((0..37).map(|i| (i * 3).to_string()).collect()).collapse(&Concat),
+        );
+        assert!(build_tree(0..0, |i: usize, _: &Concat| i, &Concat)
+            .0
+            .is_empty());
+        assert_eq!(build_tree(0..1, |i: usize, _: &Concat| i, &Concat).0, [0]);
+
+        // the panic of the leaf function is resumed with its own payload
+        let result = std::panic::catch_unwind(|| {
+            build_tree(
+                0..8,
+                |i: usize, _: &Concat| assert!(i != 5, "leaf 5"),
+                &Concat,
+            )
+        });
+        let payload = result.err().unwrap();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"leaf 5"));
+    }
+
+    #[test]
+    fn test_xmss_tree_initial_path() {
+        for &n in &[1, 2, 5, 16] {
+            let tree = XmssTree((0..n).map(|i| i.to_string()).collect::<Vec<_>>());
+            let (_, path) = tree.path_ref(&tree.0[0], &Concat);
+            assert_eq!(tree.initial_path(&Concat), path);
+            assert_eq!(path.leaf_index(), 0);
+        }
+    }
+
+    #[test]
+    fn test_cached_xmss_tree() {
+        for &n in &[1, 2, 3, 5, 16, 21] {
+            let tree = XmssTree((0..n).map(|i| i.to_string()).collect::<Vec<_>>());
+            let cached = tree.clone().cache(&Concat);
+            assert_eq!(*cached.root(), tree.clone().collapse(&Concat));
+            for index in 0..n {
+                let (_, path) = tree.clone().root_and_path(index, &Concat);
+                assert_eq!(cached.path(index as u64), path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_xmss_tree_root_and_path() {
+        for &n in &[1, 2, 7, 16] {
+            let leaves = (0..n).map(|i| i.to_string()).collect::<Vec<_>>();
+            let root = XmssTree(leaves.clone()).collapse(&Concat);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let (r, path) = XmssTree(leaves.clone()).root_and_path(index, &Concat);
+                assert_eq!(r, root);
+                assert_eq!(
+                    (r, path),
+                    XmssTree(leaves.clone()).path(leaf.clone(), &Concat)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_xmss_tree_path() {
+        for &n in &[1, 2, 3, 5, 16, 21] {
+            let leaves = (0..n).map(|i| i.to_string()).collect::<Vec<_>>();
+            let root = XmssTree(leaves.clone()).collapse(&Concat);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let (r, path) = XmssTree(leaves.clone()).path(leaf.clone(), &Concat);
+                assert_eq!(r, root);
+                assert_eq!(path.leaf_index(), index as u64);
+                assert_eq!(path.advance(leaf.clone(), &Concat), root);
+            }
+        }
+    }
+}
@@ -0,0 +1,532 @@
+use digest::generic_array::{GenericArray, typenum::Unsigned};
+use byteorder::{ByteOrder, BigEndian};
+
+use super::state::{WOtsPlus, State};
+use super::signature::Signature;
+use super::xmss::{XmssOperation, XmssPath, CachedXmssTree, par_map, MAX_HEIGHT};
+use super::error::DecodeError;
+use super::ct::ct_eq;
+
+type Block<A> = GenericArray<u8, <A as WOtsPlus>::BlockLength>;
+
+/// The complete xmss signature, the index of the one time key,
+/// its signature and the authentication path of its leaf
+#[derive(Clone)]
+pub struct XmssSignature<A, T>
+where
+    A: WOtsPlus,
+{
+    index: u64,
+    signature: Signature<A>,
+    path: XmssPath<T>,
+}
+
+impl<A, T> XmssSignature<A, T>
+where
+    A: WOtsPlus,
+{
+    pub fn new(index: u64, signature: Signature<A>, path: XmssPath<T>) -> Self {
+        XmssSignature {
+            index,
+            signature,
+            path,
+        }
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn signature(&self) -> &Signature<A> {
+        &self.signature
+    }
+
+    pub fn path(&self) -> &XmssPath<T> {
+        &self.path
+    }
+}
+
+impl<A> XmssSignature<A, GenericArray<u8, A::BlockLength>>
+where
+    A: WOtsPlus,
+{
+    /// The index as big endian `u64`, the one time signature and the siblings,
+    /// directions of the path are not stored, they are the bits of the index
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; 8];
+        BigEndian::write_u64(&mut bytes, self.index);
+        bytes.extend_from_slice(self.signature.to_bytes().as_ref());
+        self.path
+            .siblings()
+            .for_each(|other| bytes.extend_from_slice(other.as_ref()));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let n = A::BlockLength::USIZE;
+        let size = 8 + State::<A>::size();
+        if bytes.len() < size || !(bytes.len() - size).is_multiple_of(n) {
+            return Err(DecodeError::WrongLength {
+                expected: size + (bytes.len().saturating_sub(size) / n) * n,
+                got: bytes.len(),
+            });
+        }
+
+        if bytes.len() > size + MAX_HEIGHT * n {
+            return Err(DecodeError::WrongLength {
+                expected: size + MAX_HEIGHT * n,
+                got: bytes.len(),
+            });
+        }
+
+        let index = BigEndian::read_u64(&bytes[..8]);
+        let signature = Signature::from_bytes(&bytes[8..size])?;
+        let siblings = bytes[size..]
+            .chunks(n)
+            .map(GenericArray::clone_from_slice)
+            .collect();
+        Ok(XmssSignature::new(
+            index,
+            signature,
+            XmssPath::from_index(index, siblings),
+        ))
+    }
+
+    /// Verify the signature of the one time key bound to its leaf by the public seed,
+    /// as the keys of `Signer` are, see `SecretKey::with_address`, it works for
+    /// the decoded signature too, the recomputed root is compared in constant time
+    pub fn verify<F>(
+        &self,
+        public_seed: &Block<A>,
+        root: &Block<A>,
+        message: GenericArray<u8, A::MessageSize>,
+        f: &F,
+    ) -> bool
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        let signature = bind(&self.signature, public_seed, self.index);
+        verify_xmss_rootonly(root, self.index, message, &signature, &self.path, f)
+    }
+
+    /// Only for the one time keys not bound to an address, e.g. `SecretKey::from_seed`,
+    /// the keys of `Signer` are bound, their signatures never pass it once decoded
+    pub fn verify_unaddressed<F>(
+        &self,
+        root: &Block<A>,
+        message: GenericArray<u8, A::MessageSize>,
+        f: &F,
+    ) -> bool
+    where
+        F: XmssOperation<Block<A>>,
+    {
+        verify_xmss_rootonly(root, self.index, message, &self.signature, &self.path, f)
+    }
+}
+
+/// The index and the one time signature without the path, for the verifier
+/// which holds the whole tree, see `verify_against_cached_tree`
+#[derive(Clone)]
+pub struct CompactXmssSignature<A>
+where
+    A: WOtsPlus,
+{
+    index: u64,
+    signature: Signature<A>,
+}
+
+impl<A> CompactXmssSignature<A>
+where
+    A: WOtsPlus,
+{
+    pub fn new(index: u64, signature: Signature<A>) -> Self {
+        CompactXmssSignature { index, signature }
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn signature(&self) -> &Signature<A> {
+        &self.signature
+    }
+
+    /// The index as big endian `u64` and the one time signature
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; 8];
+        BigEndian::write_u64(&mut bytes, self.index);
+        bytes.extend_from_slice(self.signature.to_bytes().as_ref());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let size = 8 + State::<A>::size();
+        if bytes.len() != size {
+            return Err(DecodeError::WrongLength {
+                expected: size,
+                got: bytes.len(),
+            });
+        }
+        let index = BigEndian::read_u64(&bytes[..8]);
+        Signature::from_bytes(&bytes[8..]).map(|signature| Self::new(index, signature))
+    }
+}
+
+impl<A, T> XmssSignature<A, T>
+where
+    A: WOtsPlus,
+{
+    /// Drop the path, the verifier which holds the tree computes it again
+    pub fn compact(self) -> CompactXmssSignature<A> {
+        CompactXmssSignature::new(self.index, self.signature)
+    }
+}
+
+// the address is not serialized, it is set again from the public seed and the index
+fn bind<A>(signature: &Signature<A>, public_seed: &Block<A>, index: u64) -> Signature<A>
+where
+    A: WOtsPlus,
+{
+    signature.clone().with_address(public_seed.clone(), index)
+}
+
+/// Verify with the path from the cached tree, the result is the same
+/// as of `XmssSignature::verify` of the full signature with the path of the tree
+pub fn verify_against_cached_tree<A, F>(
+    public_seed: &Block<A>,
+    tree: &CachedXmssTree<Block<A>>,
+    message: GenericArray<u8, A::MessageSize>,
+    compact: &CompactXmssSignature<A>,
+    f: &F,
+) -> bool
+where
+    A: WOtsPlus,
+    F: XmssOperation<Block<A>>,
+{
+    if compact.index >= tree.leaves().len() as u64 {
+        return false;
+    }
+    let path = tree.path(compact.index);
+    let signature = bind(&compact.signature, public_seed, compact.index);
+    verify_xmss_rootonly(tree.root(), compact.index, message, &signature, &path, f)
+}
+
+/// The candidate leaf of the signer, the recovered public key compressed by the L-tree,
+/// for a valid signature it is the leaf of the one time key in the tree
+pub fn leaf_from_signature<A, F>(
+    message: GenericArray<u8, A::MessageSize>,
+    signature: &Signature<A>,
+    f: &F,
+) -> Block<A>
+where
+    A: WOtsPlus,
+    F: XmssOperation<Block<A>>,
+{
+    signature.recover_public_key(message).leaf(f)
+}
+
+/// Recover the leaf from the one time signature and advance the path to the root,
+/// the index should be the one of the path, the root is compared in constant time,
+/// the signature is taken as is, the decoded one of a bound key needs its address again
+pub fn verify_xmss_rootonly<A, F>(
+    root: &GenericArray<u8, A::BlockLength>,
+    index: u64,
+    message: GenericArray<u8, A::MessageSize>,
+    signature: &Signature<A>,
+    path: &XmssPath<GenericArray<u8, A::BlockLength>>,
+    f: &F,
+) -> bool
+where
+    A: WOtsPlus,
+    F: XmssOperation<GenericArray<u8, A::BlockLength>>,
+{
+    let leaf = leaf_from_signature(message, signature, f);
+    path.leaf_index() == index && ct_eq(&path.advance_ref(&leaf, f), root)
+}
+
+/// The index, the message, the one time signature and the path to verify
+pub type XmssBatchItem<A> = (
+    u64,
+    GenericArray<u8, <A as WOtsPlus>::MessageSize>,
+    Signature<A>,
+    XmssPath<Block<A>>,
+);
+
+/// Verify each `(index, message, signature, path)` against the same root, the keys
+/// are bound by the public seed as in `XmssSignature::verify`, with the `parallel`
+/// feature they are verified in several threads, the result is the same for each
+pub fn verify_xmss_batch<A, F>(
+    public_seed: &Block<A>,
+    root: &Block<A>,
+    items: &[XmssBatchItem<A>],
+    f: &F,
+) -> Vec<bool>
+where
+    A: WOtsPlus,
+    F: XmssOperation<Block<A>> + Sync,
+{
+    par_map(items, |&(index, ref message, ref signature, ref path)| {
+        let signature = bind(signature, public_seed, index);
+        verify_xmss_rootonly(root, index, message.clone(), &signature, path, f)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use digest::{
+        generic_array::{
+            GenericArray,
+            typenum::{U32, U15},
+        },
+        Digest,
+    };
+    use super::super::{sha256::Sha256, SecretKey, PublicKey, Signature, HashOperation, XmssTree};
+    use super::{
+        XmssSignature, CompactXmssSignature, verify_xmss_rootonly, verify_xmss_batch,
+        verify_against_cached_tree, leaf_from_signature,
+    };
+
+    type A = (U32, U32, U15, Sha256, ());
+    type Block = GenericArray<u8, U32>;
+
+    fn node() -> HashOperation<Sha256> {
+        HashOperation::new(b"node")
+    }
+
+    fn public_seed() -> Block {
+        Sha256::digest(b"public seed")
+    }
+
+    fn secret_key(seed: u8) -> SecretKey<A> {
+        let block = |i: usize| Sha256::digest(&[seed, i as u8]);
+        SecretKey::new(
+            (0..15).map(block).collect(),
+            (15..(15 + 67)).map(block).collect(),
+        )
+    }
+
+    // the key of the leaf `leaf`, bound to it as the keys of `Signer`
+    fn bound_key(leaf: u8) -> SecretKey<A> {
+        secret_key(leaf).with_address(public_seed(), leaf as u64)
+    }
+
+    #[test]
+    fn test_xmss_signature() {
+        let secret_keys = (0..4).map(bound_key).collect::<Vec<_>>();
+        let leaves = secret_keys
+            .iter()
+            .map(|sk| PublicKey::from_secret(sk).leaf(&node()))
+            .collect::<Vec<_>>();
+        let message = Sha256::digest(b"message");
+
+        let (root, path) = XmssTree(leaves.clone()).path(leaves[2], &node());
+        let signature = Signature::sign(&secret_keys[2], message);
+        let signature = XmssSignature::new(2, signature, path);
+        assert!(signature.verify(&public_seed(), &root, message, &node()));
+        assert!(!signature.verify(&public_seed(), &root, Sha256::digest(b"other"), &node()));
+        let other_seed = Sha256::digest(b"other public seed");
+        assert!(!signature.verify(&other_seed, &root, message, &node()));
+
+        let bytes = signature.to_bytes();
+        assert_eq!(bytes.len(), 8 + (15 + 67 + 2) * 32);
+        let decoded = XmssSignature::<A, Block>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.index(), 2);
+        assert_eq!(decoded.path(), signature.path());
+        assert!(decoded.verify(&public_seed(), &root, message, &node()));
+        // the address is lost on decoding
+        assert!(signature.verify_unaddressed(&root, message, &node()));
+        assert!(!decoded.verify_unaddressed(&root, message, &node()));
+
+        for &i in &[0, 17, 31] {
+            let mut wrong = root;
+            wrong[i] ^= 0x80;
+            assert!(!decoded.verify(&public_seed(), &wrong, message, &node()));
+        }
+
+        // the key without an address
+        let unaddressed = SecretKey::<A>::from_seed(&Sha256::digest(b"seed"));
+        let leaf = PublicKey::from_secret(&unaddressed).leaf(&node());
+        let mut leaves = leaves;
+        leaves[2] = leaf;
+        let (root, path) = XmssTree(leaves).path(leaf, &node());
+        let signature = XmssSignature::new(2, Signature::sign(&unaddressed, message), path);
+        let decoded = XmssSignature::<A, Block>::from_bytes(&signature.to_bytes()).unwrap();
+        assert!(decoded.verify_unaddressed(&root, message, &node()));
+        assert!(!decoded.verify(&public_seed(), &root, message, &node()));
+
+        assert!(XmssSignature::<A, Block>::from_bytes(&bytes[1..]).is_err());
+        let mut tall = bytes.clone();
+        tall.resize(bytes.len() + 62 * 32, 0);
+        assert!(XmssSignature::<A, Block>::from_bytes(&tall).is_err());
+        tall.truncate(bytes.len() + 61 * 32);
+        assert_eq!(
+            XmssSignature::<A, Block>::from_bytes(&tall)
+                .unwrap()
+                .path()
+                .len(),
+            63,
+        );
+    }
+
+    #[test]
+    fn test_verify_xmss_rootonly() {
+        let secret_keys = (0..5).map(secret_key).collect::<Vec<_>>();
+        let leaves = secret_keys
+            .iter()
+            .map(|sk| PublicKey::from_secret(sk).leaf(&node()))
+            .collect::<Vec<_>>();
+        let message = Sha256::digest(b"message");
+
+        for (index, secret_key) in secret_keys.iter().enumerate() {
+            let (root, path) = XmssTree(leaves.clone()).root_and_path(index, &node());
+            let signature = Signature::sign(secret_key, message);
+            let i = index as u64;
+            assert!(verify_xmss_rootonly(
+                &root,
+                i,
+                message,
+                &signature,
+                &path,
+                &node()
+            ));
+            assert!(!verify_xmss_rootonly(
+                &root,
+                i ^ 1,
+                message,
+                &signature,
+                &path,
+                &node()
+            ));
+            let other = Sha256::digest(b"other");
+            assert!(!verify_xmss_rootonly(
+                &root,
+                i,
+                other,
+                &signature,
+                &path,
+                &node()
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_xmss_batch() {
+        let secret_keys = (0..5).map(bound_key).collect::<Vec<_>>();
+        let leaves = secret_keys
+            .iter()
+            .map(|sk| PublicKey::from_secret(sk).leaf(&node()))
+            .collect::<Vec<_>>();
+        let root = XmssTree(leaves.clone()).collapse(&node());
+
+        let items = secret_keys
+            .iter()
+            .enumerate()
+            .map(|(index, secret_key)| {
+                let (_, path) = XmssTree(leaves.clone()).root_and_path(index, &node());
+                let message = Sha256::digest(&[index as u8]);
+                // the fourth signature is of another message
+                let signed = if index == 3 {
+                    Sha256::digest(b"other")
+                } else {
+                    message
+                };
+                // decoded, so without the address
+                let signature = Signature::sign(secret_key, signed).to_bytes();
+                let signature = Signature::<A>::from_bytes(&signature).unwrap();
+                (index as u64, message, signature, path)
+            })
+            .collect::<Vec<_>>();
+        let results = verify_xmss_batch(&public_seed(), &root, &items, &node());
+        assert_eq!(results, [true, true, true, false, true]);
+        for (&(index, message, ref signature, ref path), &result) in items.iter().zip(&results) {
+            let full = XmssSignature::new(index, signature.clone(), path.clone());
+            assert_eq!(full.verify(&public_seed(), &root, message, &node()), result);
+        }
+        let other_seed = Sha256::digest(b"other public seed");
+        assert_eq!(
+            verify_xmss_batch(&other_seed, &root, &items, &node()),
+            [false; 5],
+        );
+        assert!(verify_xmss_batch::<A, _>(&public_seed(), &root, &[], &node()).is_empty());
+    }
+
+    #[test]
+    fn test_verify_against_cached_tree() {
+        let secret_keys = (0..5).map(bound_key).collect::<Vec<_>>();
+        let leaves = secret_keys
+            .iter()
+            .map(|sk| PublicKey::from_secret(sk).leaf(&node()))
+            .collect::<Vec<_>>();
+        let tree = XmssTree(leaves.clone()).cache(&node());
+        let message = Sha256::digest(b"message");
+
+        for (index, secret_key) in secret_keys.iter().enumerate() {
+            let (root, path) = XmssTree(leaves.clone()).root_and_path(index, &node());
+            let signature = Signature::sign(secret_key, message);
+            let full = XmssSignature::new(index as u64, signature, path);
+            assert!(full.verify(&public_seed(), &root, message, &node()));
+
+            let bytes = full.clone().compact().to_bytes();
+            assert_eq!(bytes.len(), 8 + (15 + 67) * 32);
+            let compact = CompactXmssSignature::<A>::from_bytes(&bytes).unwrap();
+            assert_eq!(compact.index(), index as u64);
+            let seed = public_seed();
+            assert!(verify_against_cached_tree(
+                &seed,
+                &tree,
+                message,
+                &compact,
+                &node()
+            ));
+            let other = Sha256::digest(b"other");
+            assert!(!verify_against_cached_tree(
+                &seed,
+                &tree,
+                other,
+                &compact,
+                &node()
+            ));
+            let other_seed = Sha256::digest(b"other public seed");
+            assert!(!verify_against_cached_tree(
+                &other_seed,
+                &tree,
+                message,
+                &compact,
+                &node()
+            ));
+        }
+
+        let signature = Signature::sign(&secret_keys[0], message);
+        let outside = CompactXmssSignature::new(5, signature);
+        let seed = public_seed();
+        assert!(!verify_against_cached_tree(
+            &seed,
+            &tree,
+            message,
+            &outside,
+            &node()
+        ));
+        let moved = CompactXmssSignature::new(1, outside.signature().clone());
+        assert!(!verify_against_cached_tree(
+            &seed,
+            &tree,
+            message,
+            &moved,
+            &node()
+        ));
+        assert!(CompactXmssSignature::<A>::from_bytes(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_leaf_from_signature() {
+        let secret_key = secret_key(0);
+        let public_key = PublicKey::from_secret(&secret_key);
+        let message = Sha256::digest(b"message");
+        let signature = Signature::sign(&secret_key, message);
+        let leaf = leaf_from_signature(message, &signature, &node());
+        assert_eq!(leaf, public_key.leaf(&node()));
+        assert_eq!(leaf, signature.recover_public_key(message).leaf(&node()));
+        let other = leaf_from_signature(Sha256::digest(b"other"), &signature, &node());
+        assert_ne!(other, leaf);
+    }
+}